@@ -0,0 +1,163 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use async_runtime_with_mio::executor::{
+    fs::File, Builder, DriverMode, Executor, IoBackend, UdpSocket,
+};
+
+const BACKENDS: [IoBackend; 2] = [IoBackend::Epoll, IoBackend::IoUring];
+const MODES: [DriverMode; 2] = [DriverMode::ReactorThread, DriverMode::CurrentThread];
+const BATCH: usize = 32;
+const FILE_SIZE: usize = 4 << 20;
+const BLOCK: usize = 64 * 1024;
+
+fn executor(backend: IoBackend, mode: DriverMode) -> Option<Executor> {
+    let (executor, _spawner) = Builder::new()
+        .mode(mode)
+        .io_backend(backend)
+        .build()
+        .unwrap();
+
+    // without io_uring both runs would measure epoll
+    if executor.io_backend() != backend {
+        eprintln!("{backend:?} is not available, skipping");
+        return None;
+    }
+    Some(executor)
+}
+
+fn id(backend: IoBackend, mode: DriverMode) -> BenchmarkId {
+    BenchmarkId::new(format!("{backend:?}"), format!("{mode:?}"))
+}
+
+/// Binds a reversing echo server on the executor and a client socket for it.
+fn echo_pair(executor: &Executor) -> (Arc<UdpSocket>, std::net::SocketAddr) {
+    let spawner = executor.spawner();
+
+    executor.block_on(async move {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        spawner.spawn(async move {
+            let mut buf = [0; 64];
+            loop {
+                let (amt, src) = server.recv_from(&mut buf).await.unwrap();
+                buf[..amt].reverse();
+                server.send_to(&buf[..amt], src).await.unwrap();
+            }
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        (Arc::new(client), server_addr)
+    })
+}
+
+fn bench_latency(c: &mut Criterion) {
+    let mut group = c.benchmark_group("backend_udp_round_trip_latency");
+
+    for backend in BACKENDS {
+        for mode in MODES {
+            let Some(executor) = executor(backend, mode) else {
+                continue;
+            };
+            let (client, server_addr) = echo_pair(&executor);
+
+            group.bench_function(id(backend, mode), |b| {
+                b.iter_custom(|iters| {
+                    let client = client.clone();
+                    executor.block_on(async move {
+                        let mut buf = [0; 64];
+                        let start = Instant::now();
+                        for _ in 0..iters {
+                            client.send_to(b"hello world", server_addr).await.unwrap();
+                            client.recv_from(&mut buf).await.unwrap();
+                        }
+                        start.elapsed()
+                    })
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("backend_udp_echo_throughput");
+    group.throughput(Throughput::Elements(BATCH as u64));
+
+    for backend in BACKENDS {
+        for mode in MODES {
+            let Some(executor) = executor(backend, mode) else {
+                continue;
+            };
+            let (client, server_addr) = echo_pair(&executor);
+
+            group.bench_function(id(backend, mode), |b| {
+                b.iter_custom(|iters| {
+                    let client = client.clone();
+                    executor.block_on(async move {
+                        let mut buf = [0; 64];
+                        let mut elapsed = Duration::ZERO;
+                        for _ in 0..iters {
+                            let start = Instant::now();
+                            for _ in 0..BATCH {
+                                client.send_to(b"hello world", server_addr).await.unwrap();
+                            }
+                            for _ in 0..BATCH {
+                                client.recv_from(&mut buf).await.unwrap();
+                            }
+                            elapsed += start.elapsed();
+                        }
+                        elapsed
+                    })
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_file_read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("backend_file_read");
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+
+    let path = std::env::temp_dir().join(format!("backend-bench-{}", std::process::id()));
+    std::fs::write(&path, vec![7u8; FILE_SIZE]).unwrap();
+
+    for backend in BACKENDS {
+        // files never wait on the reactor, the driver makes no difference
+        let Some(executor) = executor(backend, DriverMode::CurrentThread) else {
+            continue;
+        };
+
+        group.bench_function(BenchmarkId::from_parameter(format!("{backend:?}")), |b| {
+            b.iter_custom(|iters| {
+                let path = path.clone();
+                executor.block_on(async move {
+                    let file = File::open(&path).unwrap();
+                    let mut buf = vec![0; BLOCK];
+                    let start = Instant::now();
+                    for _ in 0..iters {
+                        for offset in (0..FILE_SIZE).step_by(BLOCK) {
+                            file.read_at(&mut buf, offset as u64).await.unwrap();
+                        }
+                    }
+                    start.elapsed()
+                })
+            })
+        });
+    }
+
+    std::fs::remove_file(&path).unwrap();
+    group.finish();
+}
+
+criterion_group!(benches, bench_latency, bench_throughput, bench_file_read);
+
+criterion_main!(benches);
@@ -0,0 +1,102 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use async_runtime_with_mio::executor::{Builder, DriverMode, UdpSocket};
+
+const MODES: [DriverMode; 2] = [DriverMode::ReactorThread, DriverMode::CurrentThread];
+const BATCH: usize = 32;
+
+/// Binds a reversing echo server on the executor and a client socket for it.
+fn echo_pair(
+    mode: DriverMode,
+) -> (
+    async_runtime_with_mio::executor::Executor,
+    Arc<UdpSocket>,
+    std::net::SocketAddr,
+) {
+    let (executor, spawner) = Builder::new().mode(mode).build().unwrap();
+
+    let (client, server_addr) = executor.block_on(async move {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        spawner.spawn(async move {
+            let mut buf = [0; 64];
+            loop {
+                let (amt, src) = server.recv_from(&mut buf).await.unwrap();
+                buf[..amt].reverse();
+                server.send_to(&buf[..amt], src).await.unwrap();
+            }
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        (Arc::new(client), server_addr)
+    });
+
+    (executor, client, server_addr)
+}
+
+fn bench_latency(c: &mut Criterion) {
+    let mut group = c.benchmark_group("udp_round_trip_latency");
+
+    for mode in MODES {
+        let (executor, client, server_addr) = echo_pair(mode);
+
+        group.bench_function(BenchmarkId::from_parameter(format!("{mode:?}")), |b| {
+            b.iter_custom(|iters| {
+                let client = client.clone();
+                executor.block_on(async move {
+                    let mut buf = [0; 64];
+                    let start = Instant::now();
+                    for _ in 0..iters {
+                        client.send_to(b"hello world", server_addr).await.unwrap();
+                        client.recv_from(&mut buf).await.unwrap();
+                    }
+                    start.elapsed()
+                })
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("udp_echo_throughput");
+    group.throughput(Throughput::Elements(BATCH as u64));
+
+    for mode in MODES {
+        let (executor, client, server_addr) = echo_pair(mode);
+
+        group.bench_function(BenchmarkId::from_parameter(format!("{mode:?}")), |b| {
+            b.iter_custom(|iters| {
+                let client = client.clone();
+                executor.block_on(async move {
+                    let mut buf = [0; 64];
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iters {
+                        let start = Instant::now();
+                        for _ in 0..BATCH {
+                            client.send_to(b"hello world", server_addr).await.unwrap();
+                        }
+                        for _ in 0..BATCH {
+                            client.recv_from(&mut buf).await.unwrap();
+                        }
+                        elapsed += start.elapsed();
+                    }
+                    elapsed
+                })
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_latency, bench_throughput);
+
+criterion_main!(benches);
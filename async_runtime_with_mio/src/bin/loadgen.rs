@@ -0,0 +1,117 @@
+use std::{net::SocketAddr, process::ExitCode, time::Duration};
+
+use async_runtime_with_mio::{
+    echo::Mode,
+    executor::{Builder, DriverMode, IoBackend},
+    loadgen::{self, LoadConfig, MIN_MESSAGE_SIZE},
+};
+use clap::Parser;
+
+/// Sends UDP load to the echo server and reports throughput and latency.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// The echo server.
+    #[arg(short, long, default_value = "127.0.0.1:8000")]
+    target: SocketAddr,
+
+    /// Concurrent workers, each with its own socket and one message in
+    /// flight.
+    #[arg(short, long, default_value_t = 4)]
+    workers: usize,
+
+    /// Payload size in bytes.
+    #[arg(short, long, default_value_t = 64, value_parser = clap::value_parser!(u16).range(MIN_MESSAGE_SIZE as i64..))]
+    size: u16,
+
+    /// Messages per second across all workers; unpaced when left out.
+    #[arg(short, long, value_parser = parse_rate)]
+    rate: Option<f64>,
+
+    /// Total messages to send.
+    #[arg(short = 'n', long, default_value_t = 10_000)]
+    messages: u64,
+
+    /// Stop after this many seconds, even with messages left.
+    #[arg(short, long, value_parser = parse_seconds)]
+    duration: Option<Duration>,
+
+    /// Milliseconds to wait for a reply before counting it lost.
+    #[arg(long, default_value_t = 500)]
+    timeout: u64,
+
+    /// What the server does to each payload: echo, reverse, discard or
+    /// uppercase.
+    #[arg(short, long, default_value_t = Mode::Reverse)]
+    expect: Mode,
+
+    /// Poll the reactor on the executor thread instead of its own.
+    #[arg(long)]
+    current_thread: bool,
+
+    /// Send and receive datagrams through io_uring, where available.
+    #[arg(long)]
+    io_uring: bool,
+}
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate >= 0.0 => Ok(rate),
+        _ => Err(format!("`{s}` is not a number of messages per second")),
+    }
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("`{s}` is not a number of seconds"))
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let config = LoadConfig {
+        target: args.target,
+        workers: args.workers,
+        size: args.size.into(),
+        rate: args.rate.filter(|rate| *rate > 0.0),
+        messages: args.messages,
+        duration: args.duration,
+        timeout: Duration::from_millis(args.timeout),
+        expect: args.expect,
+    };
+
+    let mode = match args.current_thread {
+        true => DriverMode::CurrentThread,
+        false => DriverMode::ReactorThread,
+    };
+    let io_backend = match args.io_uring {
+        true => IoBackend::IoUring,
+        false => IoBackend::Epoll,
+    };
+    let (executor, spawner) = match Builder::new().mode(mode).io_backend(io_backend).build() {
+        Ok(runtime) => runtime,
+        Err(error) => {
+            eprintln!("load generator failed to start: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if executor.io_backend() != io_backend {
+        eprintln!("io_uring is not available, falling back to epoll");
+    }
+
+    let report = match executor.block_on(async move { loadgen::run(config, &spawner).await }) {
+        Ok(report) => report,
+        Err(error) => {
+            eprintln!("load generator failed: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    print!("{report}");
+
+    // a wrong reply means the server is broken, not just overloaded
+    match report.mismatched {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    }
+}
@@ -0,0 +1,316 @@
+//! Typed datagrams on top of `executor::UdpSocket`.
+//!
+//! A `Decoder` turns one received datagram into a message and an `Encoder`
+//! turns a message into one datagram. `UdpFramed` glues a codec to a socket.
+
+use std::{fmt, io, net::SocketAddr};
+
+use crate::executor::UdpSocket;
+
+/// The largest payload an IPv4 UDP datagram can carry.
+pub const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+pub trait Decoder {
+    type Item;
+    type Error: From<io::Error>;
+
+    /// Decodes one whole datagram.
+    fn decode(&mut self, datagram: &[u8]) -> Result<Self::Item, Self::Error>;
+}
+
+pub trait Encoder<Item> {
+    type Error: From<io::Error>;
+
+    /// Appends the datagram for `item` to `dst`.
+    fn encode(&mut self, item: Item, dst: &mut Vec<u8>) -> Result<(), Self::Error>;
+}
+
+/// Raw bytes in, raw bytes out.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BytesCodec;
+
+impl Decoder for BytesCodec {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn decode(&mut self, datagram: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(datagram.to_vec())
+    }
+}
+
+impl<T: AsRef<[u8]>> Encoder<T> for BytesCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: T, dst: &mut Vec<u8>) -> io::Result<()> {
+        dst.extend_from_slice(item.as_ref());
+        Ok(())
+    }
+}
+
+/// UTF-8 text, one message per datagram.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Utf8Codec;
+
+impl Decoder for Utf8Codec {
+    type Item = String;
+    type Error = io::Error;
+
+    fn decode(&mut self, datagram: &[u8]) -> io::Result<String> {
+        String::from_utf8(datagram.to_vec())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+impl<T: AsRef<str>> Encoder<T> for Utf8Codec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: T, dst: &mut Vec<u8>) -> io::Result<()> {
+        dst.extend_from_slice(item.as_ref().as_bytes());
+        Ok(())
+    }
+}
+
+/// A datagram didn't fit the receive buffer, so its tail was lost.
+///
+/// Returned inside an `io::Error` of kind `InvalidData`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Truncated {
+    pub from: SocketAddr,
+    pub buffer_size: usize,
+}
+
+impl fmt::Display for Truncated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "datagram from {} exceeds the {} byte receive buffer",
+            self.from, self.buffer_size
+        )
+    }
+}
+
+impl std::error::Error for Truncated {}
+
+/// Pairs a `UdpSocket` with a codec.
+pub struct UdpFramed<C> {
+    socket: UdpSocket,
+    codec: C,
+    buffer_size: usize,
+    skip_truncated: bool,
+    truncated: u64,
+    rd: Vec<u8>,
+    wr: Vec<u8>,
+}
+
+impl<C> UdpFramed<C> {
+    /// Frames `socket` with `codec`, accepting datagrams of up to
+    /// `MAX_DATAGRAM_SIZE` bytes.
+    pub fn new(socket: UdpSocket, codec: C) -> Self {
+        UdpFramed {
+            socket,
+            codec,
+            buffer_size: MAX_DATAGRAM_SIZE,
+            skip_truncated: false,
+            truncated: 0,
+            rd: Vec::new(),
+            wr: Vec::new(),
+        }
+    }
+
+    /// Sets the largest datagram `recv` accepts whole.
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Silently drop datagrams that don't fit instead of returning
+    /// `Truncated` errors. They are still counted by `truncated()`.
+    pub fn skip_truncated(mut self, skip: bool) -> Self {
+        self.skip_truncated = skip;
+        self
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// How many datagrams were too large for the buffer so far.
+    pub fn truncated(&self) -> u64 {
+        self.truncated
+    }
+
+    pub fn get_ref(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    pub fn codec_mut(&mut self) -> &mut C {
+        &mut self.codec
+    }
+
+    pub fn into_inner(self) -> UdpSocket {
+        self.socket
+    }
+}
+
+impl<C: Decoder> UdpFramed<C> {
+    /// Receives and decodes the next datagram.
+    pub async fn recv(&mut self) -> Result<(C::Item, SocketAddr), C::Error> {
+        // One spare byte tells an exactly-full datagram apart from a
+        // truncated one, which the socket API otherwise hides.
+        self.rd.resize(self.buffer_size + 1, 0);
+
+        loop {
+            let (amt, from) = self.socket.recv_from(&mut self.rd).await?;
+
+            if amt > self.buffer_size {
+                self.truncated += 1;
+                if self.skip_truncated {
+                    continue;
+                }
+                let truncated = Truncated {
+                    from,
+                    buffer_size: self.buffer_size,
+                };
+                return Err(io::Error::new(io::ErrorKind::InvalidData, truncated).into());
+            }
+
+            let item = self.codec.decode(&self.rd[..amt])?;
+            return Ok((item, from));
+        }
+    }
+}
+
+impl<C> UdpFramed<C> {
+    /// Encodes `item` and sends it to `dest` as one datagram.
+    pub async fn send<I>(&mut self, item: I, dest: SocketAddr) -> Result<(), C::Error>
+    where
+        C: Encoder<I>,
+    {
+        self.wr.clear();
+        self.codec.encode(item, &mut self.wr)?;
+
+        let sent = self.socket.send_to(&self.wr, dest).await?;
+        if sent != self.wr.len() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "datagram was cut short").into());
+        }
+        Ok(())
+    }
+}
+
+/// If `error` reports a truncated datagram, returns the details.
+pub fn truncation(error: &io::Error) -> Option<&Truncated> {
+    error.get_ref()?.downcast_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::Builder;
+
+    /// Big-endian u32 counters, as a stand-in for a binary protocol.
+    struct CounterCodec;
+
+    impl Decoder for CounterCodec {
+        type Item = u32;
+        type Error = io::Error;
+
+        fn decode(&mut self, datagram: &[u8]) -> io::Result<u32> {
+            let bytes = datagram
+                .try_into()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "expected 4 bytes"))?;
+            Ok(u32::from_be_bytes(bytes))
+        }
+    }
+
+    impl Encoder<u32> for CounterCodec {
+        type Error = io::Error;
+
+        fn encode(&mut self, item: u32, dst: &mut Vec<u8>) -> io::Result<()> {
+            dst.extend_from_slice(&item.to_be_bytes());
+            Ok(())
+        }
+    }
+
+    fn pair<C>(codec: C) -> (UdpFramed<C>, std::net::UdpSocket) {
+        let framed = UdpFramed::new(UdpSocket::bind("127.0.0.1:0").unwrap(), codec);
+        let peer = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.connect(framed.get_ref().local_addr().unwrap())
+            .unwrap();
+        (framed, peer)
+    }
+
+    #[test]
+    fn typed_messages_round_trip() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        let received = executor.block_on(async {
+            let (mut framed, peer) = pair(CounterCodec);
+            let peer_addr = peer.local_addr().unwrap();
+
+            framed.send(41, peer_addr).await.unwrap();
+            let mut buf = [0; 8];
+            let amt = peer.recv(&mut buf).unwrap();
+            let next = u32::from_be_bytes(buf[..amt].try_into().unwrap()) + 1;
+            peer.send(&next.to_be_bytes()).unwrap();
+
+            framed.recv().await.unwrap()
+        });
+
+        assert_eq!(received.0, 42);
+    }
+
+    #[test]
+    fn oversized_datagrams_are_reported_as_truncated() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        let (error, next, count) = executor.block_on(async {
+            let (framed, peer) = pair(Utf8Codec);
+            let mut framed = framed.with_buffer_size(4);
+
+            peer.send(b"too long").unwrap();
+            peer.send(b"fits").unwrap();
+
+            let error = framed.recv().await.unwrap_err();
+            let next = framed.recv().await.unwrap().0;
+            (error, next, framed.truncated())
+        });
+
+        let truncated = truncation(&error).expect("a truncation error");
+        assert_eq!(truncated.buffer_size, 4);
+        assert_eq!(next, "fits");
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn truncated_datagrams_can_be_skipped() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        let (message, count) = executor.block_on(async {
+            let (framed, peer) = pair(BytesCodec);
+            let mut framed = framed.with_buffer_size(4).skip_truncated(true);
+
+            peer.send(b"way too long").unwrap();
+            peer.send(b"1234").unwrap();
+
+            let message = framed.recv().await.unwrap().0;
+            (message, framed.truncated())
+        });
+
+        assert_eq!(message, b"1234");
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn decode_errors_are_returned() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        let error = executor.block_on(async {
+            let (mut framed, peer) = pair(CounterCodec);
+            peer.send(b"abc").unwrap();
+            framed.recv().await.unwrap_err()
+        });
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(truncation(&error).is_none());
+    }
+}
@@ -0,0 +1,411 @@
+//! The echo service run by the `async_runtime_with_mio` binary.
+
+use std::{
+    fmt,
+    io::{self, ErrorKind},
+    net::SocketAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    codec::{self, BytesCodec, UdpFramed},
+    executor::{
+        drain::Drain,
+        rate_limit::{KeyedRateLimiter, RateLimit},
+        time::sleep,
+        Spawner, TcpListener, TcpStream, UdpSocket,
+    },
+    http::{self, Response, Router},
+    reliable,
+};
+
+/// Prints to stderr when the configured verbosity is at least `$level`.
+macro_rules! log {
+    ($config:expr, $level:expr, $($arg:tt)*) => {
+        if $config.verbosity >= $level {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Verbosity at which connections, drops and errors are logged.
+pub const INFO: u8 = 1;
+/// Verbosity at which every message is logged.
+pub const DEBUG: u8 = 2;
+
+/// What the server sends back for each message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Echo,
+    Reverse,
+    /// Reply with nothing at all.
+    Discard,
+    /// ASCII letters are upper-cased, other bytes pass through.
+    Uppercase,
+}
+
+impl Mode {
+    /// Turns `payload` into the reply, or `None` if there is none.
+    pub fn reply(self, mut payload: Vec<u8>) -> Option<Vec<u8>> {
+        match self {
+            Mode::Echo => {}
+            Mode::Reverse => payload.reverse(),
+            Mode::Discard => return None,
+            Mode::Uppercase => payload.make_ascii_uppercase(),
+        }
+        Some(payload)
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "echo" => Ok(Mode::Echo),
+            "reverse" => Ok(Mode::Reverse),
+            "discard" => Ok(Mode::Discard),
+            "uppercase" => Ok(Mode::Uppercase),
+            _ => Err(format!(
+                "unknown mode `{s}`, expected echo, reverse, discard or uppercase"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Echo => "echo",
+            Mode::Reverse => "reverse",
+            Mode::Discard => "discard",
+            Mode::Uppercase => "uppercase",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// One reply datagram per request datagram.
+    Udp,
+    /// Each read from a connection is answered on the same connection.
+    /// TCP has no message boundaries, so `reverse` reverses per read.
+    Tcp,
+    /// The retransmitting request/response protocol from `reliable`.
+    Reliable,
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "udp" => Ok(Transport::Udp),
+            "tcp" => Ok(Transport::Tcp),
+            "reliable" => Ok(Transport::Reliable),
+            _ => Err(format!(
+                "unknown transport `{s}`, expected udp, tcp or reliable"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Transport::Udp => "udp",
+            Transport::Tcp => "tcp",
+            Transport::Reliable => "reliable",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub addr: SocketAddr,
+    pub transport: Transport,
+    pub mode: Mode,
+    /// The largest datagram accepted, or the read size for TCP.
+    pub buffer_size: usize,
+    /// Per source address, for UDP and reliable: TCP sources can't be
+    /// spoofed.
+    pub rate_limit: RateLimit,
+    /// How many sources the rate limiter tracks at once.
+    pub max_sources: usize,
+    pub verbosity: u8,
+}
+
+#[derive(Default)]
+pub struct Stats {
+    pub received: AtomicU64,
+    pub echoed: AtomicU64,
+    pub limited: AtomicU64,
+    pub truncated: AtomicU64,
+    /// Replies that could not be sent, e.g. to a spoofed source address.
+    pub failed: AtomicU64,
+}
+
+/// How long to stop accepting when the process is out of descriptors,
+/// rather than spinning on a listener that stays readable.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Whether an accept error concerns one connection or a passing lack of
+/// resources, rather than the listening socket itself.
+fn accept_error_is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset
+    ) || out_of_resources(error)
+}
+
+fn out_of_resources(error: &io::Error) -> bool {
+    matches!(
+        error.raw_os_error(),
+        Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM)
+    )
+}
+
+/// A bound but not yet serving echo server. Binding separately lets the
+/// caller learn the address when it asked for port 0.
+pub enum Listener {
+    Udp(UdpSocket),
+    Tcp(TcpListener),
+    Reliable(reliable::Server),
+}
+
+impl Listener {
+    pub fn bind(config: &Config) -> io::Result<Self> {
+        Ok(match config.transport {
+            Transport::Udp => Listener::Udp(UdpSocket::bind(config.addr)?),
+            Transport::Tcp => Listener::Tcp(TcpListener::bind(config.addr)?),
+            Transport::Reliable => Listener::Reliable(reliable::Server::bind(config.addr)?),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Listener::Udp(socket) => socket.local_addr(),
+            Listener::Tcp(listener) => listener.local_addr(),
+            Listener::Reliable(server) => server.local_addr(),
+        }
+    }
+
+    /// Serves until `drain` starts shutting down, or until an I/O error on
+    /// the listening socket.
+    ///
+    /// TCP connections are handlers on `drain`, and close once their
+    /// current request is answered. UDP and reliable requests are only
+    /// received until the shutdown starts.
+    pub async fn serve(
+        self,
+        config: Config,
+        stats: Arc<Stats>,
+        spawner: Spawner,
+        drain: Drain,
+    ) -> io::Result<()> {
+        match self {
+            Listener::Udp(socket) => serve_udp(socket, &config, &stats, &drain).await,
+            Listener::Tcp(listener) => serve_tcp(listener, config, stats, spawner, drain).await,
+            Listener::Reliable(server) => {
+                let mode = config.mode;
+                let mut limiter = KeyedRateLimiter::new(config.rate_limit, config.max_sources);
+                let limited = stats.clone();
+                let server = server.with_source_filter(move |src| {
+                    let allowed = limiter.check(&src, Instant::now());
+                    if !allowed {
+                        limited.limited.fetch_add(1, Ordering::Relaxed);
+                        log!(config, DEBUG, "rate limited {src}");
+                    }
+                    allowed
+                });
+                let serving = server.serve(spawner, move |payload, _| {
+                    stats.received.fetch_add(1, Ordering::Relaxed);
+                    stats.echoed.fetch_add(1, Ordering::Relaxed);
+                    // every request needs a response, or the client retries
+                    let reply = mode.reply(payload).unwrap_or_default();
+                    async move { reply }
+                });
+                drain.until_shutdown(serving).await.unwrap_or(Ok(()))
+            }
+        }
+    }
+}
+
+async fn serve_udp(
+    socket: UdpSocket,
+    config: &Config,
+    stats: &Stats,
+    drain: &Drain,
+) -> io::Result<()> {
+    let mut framed = UdpFramed::new(socket, BytesCodec).with_buffer_size(config.buffer_size);
+    let mut limiter = KeyedRateLimiter::new(config.rate_limit, config.max_sources);
+
+    loop {
+        let Some(received) = drain.until_shutdown(framed.recv()).await else {
+            return Ok(());
+        };
+        let (payload, src) = match received {
+            Ok(datagram) => datagram,
+            Err(error) => match codec::truncation(&error) {
+                Some(truncated) => {
+                    stats.truncated.fetch_add(1, Ordering::Relaxed);
+                    log!(config, INFO, "dropped: {truncated}");
+                    continue;
+                }
+                // ICMP errors for earlier replies surface on the next recv
+                None if error.kind() == ErrorKind::ConnectionRefused => continue,
+                None => return Err(error),
+            },
+        };
+        stats.received.fetch_add(1, Ordering::Relaxed);
+        log!(config, DEBUG, "recv from {src}: {payload:?}");
+
+        if !limiter.check(&src, Instant::now()) {
+            stats.limited.fetch_add(1, Ordering::Relaxed);
+            log!(config, DEBUG, "rate limited {src}");
+            continue;
+        }
+
+        if let Some(reply) = config.mode.reply(payload) {
+            // the source address is the sender's claim, and may be one
+            // nothing can be sent to
+            match framed.send(reply, src).await {
+                Ok(()) => stats.echoed.fetch_add(1, Ordering::Relaxed),
+                Err(error) => {
+                    log!(config, INFO, "reply to {src} failed: {error}");
+                    stats.failed.fetch_add(1, Ordering::Relaxed)
+                }
+            };
+        }
+    }
+}
+
+async fn serve_tcp(
+    listener: TcpListener,
+    config: Config,
+    stats: Arc<Stats>,
+    spawner: Spawner,
+    drain: Drain,
+) -> io::Result<()> {
+    let config = Arc::new(config);
+
+    while let Some(accepted) = drain.until_shutdown(listener.accept()).await {
+        let (stream, peer) = match accepted {
+            Ok(connection) => connection,
+            Err(error) if accept_error_is_transient(&error) => {
+                log!(config, INFO, "accept failed: {error}");
+                if out_of_resources(&error) {
+                    drain.until_shutdown(sleep(ACCEPT_BACKOFF)).await;
+                }
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        log!(config, INFO, "connection from {peer}");
+
+        let (config, stats, handler_drain) = (config.clone(), stats.clone(), drain.clone());
+        drain.spawn(&spawner, async move {
+            if let Err(error) = serve_connection(stream, &config, &stats, &handler_drain).await {
+                log!(config, INFO, "connection from {peer} failed: {error}");
+            }
+            log!(config, INFO, "connection from {peer} closed");
+        });
+    }
+    Ok(())
+}
+
+async fn serve_connection(
+    stream: TcpStream,
+    config: &Config,
+    stats: &Stats,
+    drain: &Drain,
+) -> io::Result<()> {
+    let mut buf = vec![0; config.buffer_size];
+
+    loop {
+        // an idle connection is closed as soon as the shutdown starts
+        let Some(read) = drain.until_shutdown(stream.read(&mut buf)).await else {
+            return Ok(());
+        };
+        let n = read?;
+        if n == 0 {
+            return Ok(());
+        }
+        stats.received.fetch_add(1, Ordering::Relaxed);
+        log!(config, DEBUG, "read: {:?}", &buf[..n]);
+
+        if let Some(reply) = config.mode.reply(buf[..n].to_vec()) {
+            stream.write_all(&reply).await?;
+            stats.echoed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Serves the counters as text on `GET /status`.
+pub async fn status_server(
+    addr: SocketAddr,
+    stats: Arc<Stats>,
+    spawner: Spawner,
+) -> io::Result<()> {
+    let router = Router::new().get("/status", move |_| {
+        let stats = stats.clone();
+        async move {
+            Response::text(
+                200,
+                format!(
+                    "received {}\nechoed {}\nlimited {}\ntruncated {}\nfailed {}\n",
+                    stats.received.load(Ordering::Relaxed),
+                    stats.echoed.load(Ordering::Relaxed),
+                    stats.limited.load(Ordering::Relaxed),
+                    stats.truncated.load(Ordering::Relaxed),
+                    stats.failed.load(Ordering::Relaxed),
+                ),
+            )
+        }
+    });
+
+    http::Server::bind(addr, router)?.serve(spawner).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_transform_the_payload() {
+        let payload = || b"Hello, 42!".to_vec();
+
+        assert_eq!(Mode::Echo.reply(payload()).unwrap(), b"Hello, 42!");
+        assert_eq!(Mode::Reverse.reply(payload()).unwrap(), b"!24 ,olleH");
+        assert_eq!(Mode::Uppercase.reply(payload()).unwrap(), b"HELLO, 42!");
+        assert_eq!(Mode::Discard.reply(payload()), None);
+    }
+
+    #[test]
+    fn modes_and_transports_round_trip_through_strings() {
+        for mode in [Mode::Echo, Mode::Reverse, Mode::Discard, Mode::Uppercase] {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+        for transport in [Transport::Udp, Transport::Tcp, Transport::Reliable] {
+            assert_eq!(transport.to_string().parse(), Ok(transport));
+        }
+        assert!("shout".parse::<Mode>().is_err());
+    }
+
+    #[test]
+    fn only_per_connection_and_resource_accept_errors_are_transient() {
+        let transient = |code| accept_error_is_transient(&io::Error::from_raw_os_error(code));
+
+        assert!([libc::EMFILE, libc::ENFILE, libc::ECONNABORTED]
+            .into_iter()
+            .all(transient));
+        assert!(![libc::EBADF, libc::EINVAL, libc::ENOTSOCK]
+            .into_iter()
+            .any(transient));
+    }
+}
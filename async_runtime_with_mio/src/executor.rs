@@ -0,0 +1,769 @@
+use std::{
+    cell::RefCell,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    task::{Context, RawWaker, RawWakerVTable, Wake, Waker},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+#[cfg(unix)]
+mod async_fd;
+mod coop;
+pub mod drain;
+mod error;
+#[cfg(unix)]
+pub mod fs;
+mod local;
+mod net;
+mod priority;
+pub mod rate_limit;
+mod reactor;
+pub mod schedule;
+#[cfg(unix)]
+pub mod serial;
+#[cfg(unix)]
+pub mod signal;
+pub mod stream;
+pub mod sync;
+pub mod time;
+mod tls;
+mod trace;
+#[cfg(target_os = "linux")]
+mod uring;
+
+#[cfg(unix)]
+pub use async_fd::{AsyncFd, ReadyGuard};
+pub use coop::{yield_now, YieldNow};
+pub use error::Error;
+pub use local::spawn_local;
+pub use net::{Incoming, TcpListener, TcpStream, UdpSocket};
+pub use priority::Priority;
+pub use reactor::{Direction, Reactor};
+pub use stream::Stream;
+pub use time::{interval, sleep, sleep_until, timeout};
+pub use tls::TlsStream;
+
+use priority::ReadyQueue;
+use reactor::Unparker;
+use trace::{task_event, TaskMeta};
+
+/// Tasks polled between two non-blocking reactor turns in current-thread
+/// mode.
+const EVENT_INTERVAL: u32 = 61;
+
+/// Locks one of the runtime's own mutexes. A panicking task never holds
+/// them, and their critical sections leave the data consistent, so a
+/// poisoned lock is still safe to use.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Begin Implementing The Executor
+pub(crate) struct Task {
+    // `None` once the future has completed; a task can still be woken (and
+    // queued) after that, e.g. by its read and write wakers both firing.
+    future: Mutex<Option<Pin<Box<dyn Future<Output = ()> + Send + 'static>>>>,
+    priority: Priority,
+    // only read by `tracing` events, and empty without them
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    meta: TaskMeta,
+    spawner: Spawner,
+}
+
+/// Where the executor gets its I/O events from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DriverMode {
+    /// A dedicated `reactor` thread blocks in `mio::Poll` and wakes tasks
+    /// across threads into the ready queue.
+    #[default]
+    ReactorThread,
+    /// The executor polls `mio::Poll` itself whenever its ready queue is
+    /// empty, so I/O wakeups never leave the executor thread.
+    CurrentThread,
+}
+
+/// Which kernel interface carries UDP and file I/O.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IoBackend {
+    /// Readiness through `epoll`: wait until a socket is ready, then make
+    /// the syscall. Files are read and written in place.
+    #[default]
+    Epoll,
+    /// Completion-based io_uring, on Linux, for UDP sends and receives and
+    /// for file reads and writes; everything else keeps using `epoll`.
+    ///
+    /// Falls back to `Epoll` where io_uring is missing or forbidden, e.g.
+    /// by a container's seccomp filter.
+    IoUring,
+}
+
+/// How the executor builds the waker it hands to each task.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WakerKind {
+    /// The hand-written `RawWakerVTable` over `Arc<Task>`.
+    #[default]
+    Raw,
+    /// `Waker::from(Arc<Task>)`, through the `Wake` impl, with no unsafe
+    /// code of our own.
+    Wake,
+}
+
+/// The ready queue shared by the executor and every spawner.
+struct Shared {
+    queue: Mutex<ReadyQueue>,
+    // Number of live `Spawner`s, including the ones owned by tasks.
+    // The executor stops once this reaches zero and the queue is drained.
+    spawners: AtomicUsize,
+    notify: Notify,
+}
+
+enum Notify {
+    Condvar(Condvar),
+    Reactor(Arc<Unparker>),
+}
+
+impl Shared {
+    fn push(&self, task: Arc<Task>) {
+        lock(&self.queue).push(task);
+        self.notify();
+    }
+
+    fn notify(&self) {
+        match &self.notify {
+            Notify::Condvar(condvar) => {
+                // take the lock so the notification can't slip in between
+                // the executor's emptiness check and its wait
+                std::mem::drop(lock(&self.queue));
+                condvar.notify_one();
+            }
+            Notify::Reactor(unparker) => unparker.wake(),
+        }
+    }
+}
+
+enum Driver {
+    ReactorThread(Option<JoinHandle<()>>),
+    CurrentThread(RefCell<(mio::Poll, mio::Events)>),
+}
+
+pub struct Executor {
+    shared: Arc<Shared>,
+    reactor: Arc<reactor::Reactor>,
+    driver: Driver,
+    waker: WakerKind,
+}
+
+impl Executor {
+    pub fn run(&self) {
+        self.run_until(|| false)
+    }
+
+    /// Runs the executor until `future` completes and returns its output.
+    ///
+    /// Other spawned tasks keep making progress meanwhile, and are left in
+    /// place when this returns.
+    pub fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let output = Arc::new(Mutex::new(None));
+        let slot = output.clone();
+
+        self.spawner().spawn_named("block_on", async move {
+            let value = future.await;
+            *lock(&slot) = Some(value);
+        });
+
+        self.run_until(|| lock(&output).is_some());
+
+        let value = lock(&output).take();
+        value.expect("block_on future was dropped before completing")
+    }
+
+    /// Like `block_on`, for a future that isn't `Send`.
+    ///
+    /// The future, and anything it passes to `spawn_local`, is only ever
+    /// polled on the current thread.
+    pub fn block_on_local<F>(&self, future: F) -> F::Output
+    where
+        F: Future + 'static,
+    {
+        let output = Rc::new(RefCell::new(None));
+        let slot = output.clone();
+
+        local::spawn(&self.spawner(), "block_on_local", async move {
+            let value = future.await;
+            *slot.borrow_mut() = Some(value);
+        });
+
+        self.run_until(|| output.borrow().is_some());
+
+        let value = output.borrow_mut().take();
+        value.expect("block_on_local future was dropped before completing")
+    }
+
+    /// Returns a new spawner for this executor.
+    pub fn spawner(&self) -> Spawner {
+        Spawner::new(self.shared.clone())
+    }
+
+    /// The I/O backend in use, which is `Epoll` if io_uring was asked for
+    /// and isn't available.
+    pub fn io_backend(&self) -> IoBackend {
+        self.reactor.io_backend()
+    }
+
+    fn run_until(&self, mut done: impl FnMut() -> bool) {
+        let _enter = self.reactor.enter();
+        let _local = local::enter(self.shared.clone());
+        let mut polled = 0u32;
+
+        while !done() {
+            // a queue that never drains would otherwise keep the
+            // current-thread driver from ever looking at the reactor
+            polled = polled.wrapping_add(1);
+            if polled.is_multiple_of(EVENT_INTERVAL) {
+                self.poll_events();
+            }
+
+            let Some(task) = self.next_task() else {
+                return;
+            };
+
+            // a task that panicked while being polled is not polled again
+            let Ok(mut slot) = task.future.lock() else {
+                task_event!(debug, task.meta, "skipping a task that panicked");
+                continue;
+            };
+            let Some(future) = slot.as_mut() else {
+                continue;
+            };
+
+            // make a context (explained later)
+            let waker = match self.waker {
+                WakerKind::Raw => Arc::clone(&task).waker(),
+                WakerKind::Wake => Arc::clone(&task).safe_waker(),
+            };
+            let mut context = Context::from_waker(&waker);
+
+            // allow the future some CPU time to make progress
+            task_event!(trace, task.meta, "polling task");
+            if coop::with_budget(|| future.as_mut().poll(&mut context)).is_ready() {
+                task_event!(debug, task.meta, "task completed");
+                *slot = None;
+            }
+        }
+    }
+
+    /// Collects I/O events and expired timers without blocking.
+    fn poll_events(&self) {
+        if let Driver::CurrentThread(driver) = &self.driver {
+            let mut driver = driver.borrow_mut();
+            let (poll, events) = &mut *driver;
+            self.reactor.turn(poll, events, Some(Duration::ZERO));
+        }
+    }
+
+    /// Pops the next ready task, sleeping until one is woken.
+    ///
+    /// Returns `None` once no spawner is left to ever produce a task.
+    fn next_task(&self) -> Option<Arc<Task>> {
+        match (&self.driver, &self.shared.notify) {
+            (Driver::CurrentThread(driver), Notify::Reactor(unparker)) => {
+                let mut driver = driver.borrow_mut();
+                let (poll, events) = &mut *driver;
+
+                loop {
+                    if let Some(task) = lock(&self.shared.queue).pop() {
+                        return Some(task);
+                    }
+                    if self.shared.spawners.load(Ordering::SeqCst) == 0 {
+                        return None;
+                    }
+
+                    // Publish that we are about to sleep, then look again: a
+                    // task pushed after this point will wake the poll.
+                    unparker.park();
+                    let empty = lock(&self.shared.queue).is_empty();
+                    let idle = self.shared.spawners.load(Ordering::SeqCst) == 0;
+                    if empty && !idle {
+                        self.reactor.turn(poll, events, None);
+                    }
+                    unparker.unpark();
+                }
+            }
+            (_, Notify::Condvar(condvar)) => {
+                let mut queue = lock(&self.shared.queue);
+
+                loop {
+                    if let Some(task) = queue.pop() {
+                        return Some(task);
+                    }
+                    if self.shared.spawners.load(Ordering::SeqCst) == 0 {
+                        return None;
+                    }
+                    queue = condvar.wait(queue).unwrap_or_else(PoisonError::into_inner);
+                }
+            }
+            _ => unreachable!("driver and notify are always built together"),
+        }
+    }
+}
+
+impl Drop for Executor {
+    fn drop(&mut self) {
+        if let Driver::ReactorThread(thread) = &mut self.driver {
+            self.reactor.shutdown();
+            if let Some(thread) = thread.take() {
+                let _ = thread.join();
+            }
+        }
+
+        // Tasks that are still pending are never polled again. Drop them
+        // (and the spawners they own) instead of leaking them.
+        self.reactor.clear();
+        let queue = std::mem::take(&mut *lock(&self.shared.queue));
+        std::mem::drop(queue);
+    }
+}
+
+/// Configures how an executor is driven before creating it.
+#[derive(Debug, Default)]
+pub struct Builder {
+    mode: DriverMode,
+    waker: WakerKind,
+    io_backend: IoBackend,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drive the reactor on the executor thread.
+    pub fn current_thread(mut self) -> Self {
+        self.mode = DriverMode::CurrentThread;
+        self
+    }
+
+    /// Drive the reactor on a dedicated `reactor` thread (the default).
+    pub fn reactor_thread(mut self) -> Self {
+        self.mode = DriverMode::ReactorThread;
+        self
+    }
+
+    pub fn mode(mut self, mode: DriverMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Choose how task wakers are built; both behave the same.
+    pub fn waker(mut self, waker: WakerKind) -> Self {
+        self.waker = waker;
+        self
+    }
+
+    /// Choose how UDP and file I/O reach the kernel; sockets and files keep
+    /// the same API either way.
+    pub fn io_backend(mut self, io_backend: IoBackend) -> Self {
+        self.io_backend = io_backend;
+        self
+    }
+
+    pub fn build(self) -> Result<(Executor, Spawner), Error> {
+        let (reactor, poll) = reactor::Reactor::new(self.io_backend).map_err(Error::Reactor)?;
+
+        let (notify, driver) = match self.mode {
+            DriverMode::ReactorThread => {
+                let thread = reactor.spawn_thread(poll).map_err(Error::Thread)?;
+                (
+                    Notify::Condvar(Condvar::new()),
+                    Driver::ReactorThread(Some(thread)),
+                )
+            }
+            DriverMode::CurrentThread => {
+                let events = mio::Events::with_capacity(1024);
+                (
+                    Notify::Reactor(reactor.unparker()),
+                    Driver::CurrentThread(RefCell::new((poll, events))),
+                )
+            }
+        };
+
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(ReadyQueue::default()),
+            spawners: AtomicUsize::new(0),
+            notify,
+        });
+
+        let spawner = Spawner::new(shared.clone());
+
+        Ok((
+            Executor {
+                shared,
+                reactor,
+                driver,
+                waker: self.waker,
+            },
+            spawner,
+        ))
+    }
+}
+
+// Begin Implementing a Spawner
+pub struct Spawner {
+    shared: Arc<Shared>,
+}
+
+pub fn new_executor_spawner() -> Result<(Executor, Spawner), Error> {
+    Builder::new().build()
+}
+
+impl Spawner {
+    fn new(shared: Arc<Shared>) -> Self {
+        shared.spawners.fetch_add(1, Ordering::SeqCst);
+        Spawner { shared }
+    }
+
+    pub fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
+        self.spawn_with_priority(Priority::Normal, future)
+    }
+
+    /// Spawns a task that `tracing` events call `name`, instead of by the
+    /// type of its future.
+    pub fn spawn_named(
+        &self,
+        name: &'static str,
+        future: impl Future<Output = ()> + Send + 'static,
+    ) {
+        self.spawn_with_meta(Priority::Normal, TaskMeta::new(name), future)
+    }
+
+    /// Spawns a task that is scheduled according to `priority`, and keeps
+    /// it every time it is woken.
+    pub fn spawn_with_priority(
+        &self,
+        priority: Priority,
+        future: impl Future<Output = ()> + Send + 'static,
+    ) {
+        let name = std::any::type_name_of_val(&future);
+        self.spawn_with_meta(priority, TaskMeta::new(name), future)
+    }
+
+    pub(crate) fn spawn_with_meta(
+        &self,
+        priority: Priority,
+        meta: TaskMeta,
+        future: impl Future<Output = ()> + Send + 'static,
+    ) {
+        task_event!(debug, meta, ?priority, "task spawned");
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(future))),
+            priority,
+            meta,
+            spawner: self.clone(),
+        });
+        self.spawn_task(task)
+    }
+
+    pub(crate) fn spawn_task(&self, task: Arc<Task>) {
+        self.shared.push(task);
+    }
+}
+
+impl Clone for Spawner {
+    fn clone(&self) -> Self {
+        Spawner::new(self.shared.clone())
+    }
+}
+
+impl Drop for Spawner {
+    fn drop(&mut self) {
+        // the last spawner going away may be what the executor waits for
+        if self.shared.spawners.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.shared.notify();
+        }
+    }
+}
+
+// Begin Constructing a Waker
+fn clone(ptr: *const ()) -> RawWaker {
+    let ori: Arc<Task> = unsafe { Arc::from_raw(ptr as _) };
+
+    // Increment the inner counter of the arc.
+    let cloned = ori.clone();
+
+    std::mem::forget(ori);
+    std::mem::forget(cloned);
+
+    RawWaker::new(ptr, &Task::WAKER_VTABLE)
+}
+
+fn drop(ptr: *const ()) {
+    let _: Arc<Task> = unsafe { Arc::from_raw(ptr as _) };
+}
+
+fn wake(ptr: *const ()) {
+    let arc: Arc<Task> = unsafe { Arc::from_raw(ptr as _) };
+    task_event!(trace, arc.meta, "task woken");
+    let spawner = arc.spawner.clone();
+
+    spawner.spawn_task(arc);
+}
+
+fn wake_by_ref(ptr: *const ()) {
+    let arc: Arc<Task> = unsafe { Arc::from_raw(ptr as _) };
+    task_event!(trace, arc.meta, "task woken");
+
+    arc.spawner.spawn_task(arc.clone());
+
+    // we don't actually have ownership of this arc value
+    // therefore we must not drop `arc`
+    std::mem::forget(arc)
+}
+
+impl Task {
+    const WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop);
+
+    /*
+    Why is all this unsafe pointer business required here? It looks like the code could use a Wake trait instead.
+    A `Waker` has to erase the task's type, and a trait with a `Clone` bound can't be made into an object, so
+    wakers used to require a manual vtable. `std::task::Wake` now does exactly this for any `Arc<impl Wake>`:
+    see `safe_waker` below. The raw vtable stays as the default, to keep the mechanism visible, and the two
+    are checked against each other in the tests.
+    */
+    pub fn waker(self: Arc<Self>) -> Waker {
+        let opaque_ptr = Arc::into_raw(self) as *const ();
+        let vtable = &Self::WAKER_VTABLE;
+
+        unsafe { Waker::from_raw(RawWaker::new(opaque_ptr, vtable)) }
+    }
+
+    /// The same waker, built by the standard library from the `Wake` impl.
+    pub fn safe_waker(self: Arc<Self>) -> Waker {
+        Waker::from(self)
+    }
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        task_event!(trace, self.meta, "task woken");
+        let spawner = self.spawner.clone();
+
+        spawner.spawn_task(self);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        task_event!(trace, self.meta, "task woken");
+        self.spawner.spawn_task(self.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo_round_trip(mode: DriverMode) {
+        echo_round_trip_with(mode, WakerKind::default());
+    }
+
+    fn echo_round_trip_with(mode: DriverMode, waker: WakerKind) {
+        let (executor, spawner) = Builder::new().mode(mode).waker(waker).build().unwrap();
+
+        let reply = executor.block_on(async move {
+            let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let server_addr = server.local_addr().unwrap();
+
+            spawner.spawn(async move {
+                let mut buf = [0; 16];
+                let (amt, src) = server.recv_from(&mut buf).await.unwrap();
+                buf[..amt].reverse();
+                server.send_to(&buf[..amt], src).await.unwrap();
+            });
+
+            let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+            client.send_to(b"hello", server_addr).await.unwrap();
+
+            let mut buf = [0; 16];
+            let (amt, _) = client.recv_from(&mut buf).await.unwrap();
+            buf[..amt].to_vec()
+        });
+
+        assert_eq!(reply, b"olleh");
+    }
+
+    /// A task on a bare ready queue, with no reactor or threads involved, so
+    /// that the waker tests also run under Miri (`just miri`).
+    fn detached_task() -> (Arc<Shared>, Arc<Task>) {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(ReadyQueue::default()),
+            spawners: AtomicUsize::new(0),
+            notify: Notify::Condvar(Condvar::new()),
+        });
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(async {}))),
+            priority: Priority::Normal,
+            meta: TaskMeta::new("detached"),
+            spawner: Spawner::new(shared.clone()),
+        });
+        (shared, task)
+    }
+
+    fn waker_of(task: &Arc<Task>, kind: WakerKind) -> Waker {
+        match kind {
+            WakerKind::Raw => task.clone().waker(),
+            WakerKind::Wake => task.clone().safe_waker(),
+        }
+    }
+
+    /// Empties the queue, whose tasks would otherwise keep `shared` alive.
+    fn drain(shared: &Shared) -> Vec<Arc<Task>> {
+        let mut queue = lock(&shared.queue);
+        std::iter::from_fn(|| queue.pop()).collect()
+    }
+
+    const WAKER_KINDS: [WakerKind; 2] = [WakerKind::Raw, WakerKind::Wake];
+
+    #[test]
+    fn waker_clones_and_drops_balance_the_task_count() {
+        for kind in WAKER_KINDS {
+            let (_shared, task) = detached_task();
+
+            let waker = waker_of(&task, kind);
+            assert_eq!(Arc::strong_count(&task), 2, "{kind:?}");
+
+            let cloned = waker.clone();
+            assert_eq!(Arc::strong_count(&task), 3, "{kind:?}");
+            assert!(cloned.will_wake(&waker));
+
+            std::mem::drop(cloned);
+            assert_eq!(Arc::strong_count(&task), 2, "{kind:?}");
+            std::mem::drop(waker);
+            assert_eq!(Arc::strong_count(&task), 1, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn wake_by_ref_queues_a_new_reference() {
+        for kind in WAKER_KINDS {
+            let (shared, task) = detached_task();
+            let waker = waker_of(&task, kind);
+
+            waker.wake_by_ref();
+            waker.wake_by_ref();
+            assert_eq!(Arc::strong_count(&task), 4, "{kind:?}");
+
+            let queued = drain(&shared);
+            assert_eq!(queued.len(), 2);
+            assert!(queued.iter().all(|queued| Arc::ptr_eq(queued, &task)));
+            std::mem::drop(queued);
+
+            std::mem::drop(waker);
+            assert_eq!(Arc::strong_count(&task), 1, "{kind:?}");
+            assert_eq!(shared.spawners.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn wake_moves_its_reference_into_the_queue() {
+        for kind in WAKER_KINDS {
+            let (shared, task) = detached_task();
+            let waker = waker_of(&task, kind);
+            let cloned = waker.clone();
+
+            waker.wake();
+            assert_eq!(Arc::strong_count(&task), 3, "{kind:?}");
+            cloned.wake();
+            assert_eq!(Arc::strong_count(&task), 3, "{kind:?}");
+
+            assert_eq!(drain(&shared).len(), 2);
+            assert_eq!(Arc::strong_count(&task), 1, "{kind:?}");
+            // the spawner cloned to push the task is gone again
+            assert_eq!(shared.spawners.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn reactor_thread_driver_echoes_a_datagram() {
+        echo_round_trip(DriverMode::ReactorThread);
+    }
+
+    #[test]
+    fn current_thread_driver_echoes_a_datagram() {
+        echo_round_trip(DriverMode::CurrentThread);
+    }
+
+    #[test]
+    fn the_wake_impl_drives_both_drivers() {
+        echo_round_trip_with(DriverMode::ReactorThread, WakerKind::Wake);
+        echo_round_trip_with(DriverMode::CurrentThread, WakerKind::Wake);
+    }
+
+    #[test]
+    fn run_returns_once_every_spawner_and_task_is_gone() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..3 {
+            let counter = counter.clone();
+            spawner.spawn(async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        std::mem::drop(spawner);
+        executor.run();
+
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn current_thread_driver_wakes_on_spawn_from_another_thread() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        let remote = spawner.clone();
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            remote.spawn(async move { sender.send(42).unwrap() });
+        });
+
+        std::mem::drop(spawner);
+        executor.run();
+        thread.join().unwrap();
+
+        assert_eq!(receiver.recv().unwrap(), 42);
+    }
+
+    #[test]
+    fn a_panicking_task_does_not_poison_the_executor() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        // arms a timer, so the task is woken again after it panicked
+        spawner.spawn(async {
+            let mut timer = std::pin::pin!(time::sleep(Duration::from_millis(5)));
+            std::future::poll_fn(|cx| {
+                let _ = timer.as_mut().poll(cx);
+                panic!("task failed");
+            })
+            .await
+        });
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            executor.block_on(async {})
+        }));
+        assert!(panicked.is_err());
+
+        let value = executor.block_on(async {
+            time::sleep(Duration::from_millis(20)).await;
+            7
+        });
+        assert_eq!(value, 7);
+    }
+}
@@ -0,0 +1,277 @@
+//! Readiness for any file descriptor, for I/O sources the runtime has no
+//! type of its own for: serial ports, `eventfd`, `inotify`, pipes...
+//!
+//! `AsyncFd` only reports readiness; reading and writing stay with the
+//! wrapped value, which must be in non-blocking mode.
+
+use std::{
+    io::{self, ErrorKind},
+    os::fd::AsRawFd,
+    task::{ready, Context, Poll},
+};
+
+use mio::{unix::SourceFd, Interest};
+
+use super::reactor::{Direction, Registration};
+
+/// A file descriptor registered with the reactor of the current executor.
+pub struct AsyncFd<T: AsRawFd> {
+    // `None` only once `into_inner` has taken it
+    inner: Option<T>,
+    registration: Registration,
+}
+
+impl<T: AsRawFd> AsyncFd<T> {
+    /// Registers `inner` for both read and write readiness.
+    ///
+    /// Fails if the descriptor can't be polled, e.g. a regular file.
+    pub fn new(inner: T) -> io::Result<Self> {
+        let fd = inner.as_raw_fd();
+        let registration = Registration::new(
+            &mut SourceFd(&fd),
+            Interest::READABLE | Interest::WRITABLE,
+        )?;
+
+        Ok(AsyncFd {
+            inner: Some(inner),
+            registration,
+        })
+    }
+
+    pub fn get_ref(&self) -> &T {
+        self.inner.as_ref().unwrap()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Deregisters the descriptor and hands it back.
+    pub fn into_inner(mut self) -> T {
+        self.deregister();
+        self.inner.take().unwrap()
+    }
+
+    /// Waits until the descriptor may be readable.
+    pub async fn readable(&self) -> io::Result<ReadyGuard<'_, T>> {
+        std::future::poll_fn(|cx| self.poll_read_ready(cx)).await
+    }
+
+    /// Waits until the descriptor may be writable.
+    pub async fn writable(&self) -> io::Result<ReadyGuard<'_, T>> {
+        std::future::poll_fn(|cx| self.poll_write_ready(cx)).await
+    }
+
+    pub fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<ReadyGuard<'_, T>>> {
+        self.poll_ready(Direction::Read, cx)
+    }
+
+    pub fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<ReadyGuard<'_, T>>> {
+        self.poll_ready(Direction::Write, cx)
+    }
+
+    fn poll_ready(
+        &self,
+        direction: Direction,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<ReadyGuard<'_, T>>> {
+        let tick = ready!(self.registration.poll_ready(direction, cx))?;
+
+        Poll::Ready(Ok(ReadyGuard {
+            fd: self,
+            direction,
+            tick,
+        }))
+    }
+
+    /// Runs the non-blocking operation `f` on the descriptor until it stops
+    /// returning `WouldBlock`, waiting for `direction` in between.
+    pub async fn async_io<R>(
+        &self,
+        direction: Direction,
+        mut f: impl FnMut(&T) -> io::Result<R>,
+    ) -> io::Result<R> {
+        self.registration
+            .async_io(direction, || f(self.get_ref()))
+            .await
+    }
+
+    fn deregister(&mut self) {
+        if let Some(inner) = &self.inner {
+            let fd = inner.as_raw_fd();
+            let _ = self.registration.deregister(&mut SourceFd(&fd));
+        }
+    }
+}
+
+impl<T: AsRawFd> Drop for AsyncFd<T> {
+    fn drop(&mut self) {
+        // before `inner` closes the descriptor
+        self.deregister();
+    }
+}
+
+/// Readiness reported by `AsyncFd::readable` or `writable`.
+///
+/// The readiness stays set until an operation hits `WouldBlock`: either
+/// through `try_io`, or by calling `clear_ready` after seeing it yourself.
+/// Otherwise the next wait returns straight away.
+pub struct ReadyGuard<'a, T: AsRawFd> {
+    fd: &'a AsyncFd<T>,
+    direction: Direction,
+    tick: usize,
+}
+
+impl<T: AsRawFd> ReadyGuard<'_, T> {
+    pub fn get_inner(&self) -> &T {
+        self.fd.get_ref()
+    }
+
+    /// Forgets the readiness, unless an event has arrived since it was
+    /// reported.
+    pub fn clear_ready(&mut self) {
+        self.fd
+            .registration
+            .clear_ready(self.direction, self.tick)
+    }
+
+    /// Runs `f` on the descriptor, clearing the readiness if it returns
+    /// `WouldBlock`.
+    pub fn try_io<R>(&mut self, f: impl FnOnce(&T) -> io::Result<R>) -> io::Result<R> {
+        let result = f(self.fd.get_ref());
+        if matches!(&result, Err(error) if error.kind() == ErrorKind::WouldBlock) {
+            self.clear_ready();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        os::fd::{FromRawFd, OwnedFd},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::executor::{sleep, Builder, DriverMode};
+
+    fn set_nonblocking(fd: &impl AsRawFd) {
+        let fd = fd.as_raw_fd();
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            assert_eq!(libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK), 0);
+        }
+    }
+
+    fn eventfd() -> OwnedFd {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        assert!(fd >= 0, "{}", io::Error::last_os_error());
+        unsafe { OwnedFd::from_raw_fd(fd) }
+    }
+
+    #[test]
+    fn a_pipe_becomes_readable_when_written() {
+        for mode in [DriverMode::ReactorThread, DriverMode::CurrentThread] {
+            let (executor, spawner) = Builder::new().mode(mode).build().unwrap();
+
+            let received = executor.block_on(async move {
+                let (reader, mut writer) = io::pipe().unwrap();
+                set_nonblocking(&reader);
+                let reader = AsyncFd::new(reader).unwrap();
+
+                spawner.spawn(async move {
+                    for message in [&b"hello "[..], b"pipe"] {
+                        sleep(Duration::from_millis(5)).await;
+                        writer.write_all(message).unwrap();
+                    }
+                });
+
+                let mut received = Vec::new();
+                let mut buf = [0; 16];
+                loop {
+                    let mut guard = reader.readable().await.unwrap();
+                    match guard.try_io(|mut reader| reader.read(&mut buf)) {
+                        Ok(0) => return received,
+                        Ok(n) => received.extend_from_slice(&buf[..n]),
+                        Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                        Err(error) => panic!("{error}"),
+                    }
+                }
+            });
+
+            assert_eq!(received, b"hello pipe", "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn an_eventfd_counts_across_tasks() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let total = executor.block_on(async move {
+            let events = std::sync::Arc::new(AsyncFd::new(eventfd()).unwrap());
+
+            for n in 1..=3u64 {
+                let events = events.clone();
+                spawner.spawn(async move {
+                    let mut guard = events.writable().await.unwrap();
+                    guard
+                        .try_io(|fd| {
+                            let written = unsafe {
+                                libc::write(fd.as_raw_fd(), n.to_ne_bytes().as_ptr().cast(), 8)
+                            };
+                            match written {
+                                8 => Ok(()),
+                                _ => Err(io::Error::last_os_error()),
+                            }
+                        })
+                        .unwrap();
+                });
+            }
+
+            sleep(Duration::from_millis(10)).await;
+            events
+                .async_io(Direction::Read, |fd| {
+                    let mut value = [0; 8];
+                    let read = unsafe { libc::read(fd.as_raw_fd(), value.as_mut_ptr().cast(), 8) };
+                    match read {
+                        8 => Ok(u64::from_ne_bytes(value)),
+                        _ => Err(io::Error::last_os_error()),
+                    }
+                })
+                .await
+                .unwrap()
+        });
+
+        assert_eq!(total, 6);
+    }
+
+    #[test]
+    fn into_inner_hands_back_a_working_descriptor() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        executor.block_on(async {
+            let (reader, mut writer) = io::pipe().unwrap();
+            set_nonblocking(&reader);
+
+            let reader = AsyncFd::new(reader).unwrap().into_inner();
+            // epoll refuses to register the same descriptor twice
+            let mut reader = AsyncFd::new(reader).unwrap().into_inner();
+
+            writer.write_all(b"x").unwrap();
+            let mut buf = [0; 1];
+            assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn regular_files_are_rejected() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        executor.block_on(async {
+            let file = std::fs::File::open("Cargo.toml").unwrap();
+            assert!(AsyncFd::new(file).is_err());
+        });
+    }
+}
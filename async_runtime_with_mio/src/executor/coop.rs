@@ -0,0 +1,152 @@
+//! Cooperative scheduling.
+//!
+//! A future only gives the executor back control by returning `Pending`.
+//! A task reading from a socket that is always ready never does, and
+//! starves every other task. Each poll of a task therefore gets a budget
+//! of I/O operations; once it is spent, I/O futures return `Pending`
+//! (after rescheduling the task) until the task is polled again.
+
+use std::{
+    cell::Cell,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// I/O operations a task may perform per poll.
+pub(crate) const BUDGET: u32 = 128;
+
+thread_local! {
+    // `None` outside of a task, where nothing is limited
+    static REMAINING: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+/// Runs `f`, a poll of one task, with a fresh budget.
+pub(crate) fn with_budget<R>(f: impl FnOnce() -> R) -> R {
+    struct Reset(Option<u32>);
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            REMAINING.with(|remaining| remaining.set(self.0));
+        }
+    }
+
+    let _reset = Reset(REMAINING.with(|remaining| remaining.replace(Some(BUDGET))));
+    f()
+}
+
+/// Spends one unit of the current task's budget, or reschedules the task
+/// and returns `Pending` if there is none left.
+pub(crate) fn poll_proceed(cx: &mut Context<'_>) -> Poll<()> {
+    REMAINING.with(|remaining| match remaining.get() {
+        Some(0) => {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+        Some(n) => {
+            remaining.set(Some(n - 1));
+            Poll::Ready(())
+        }
+        None => Poll::Ready(()),
+    })
+}
+
+/// Lets the executor run other tasks before continuing.
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        // back of the queue
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::executor::{sleep, Builder, DriverMode, UdpSocket};
+
+    #[test]
+    fn yielding_tasks_take_turns() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        for name in ['a', 'b'] {
+            let order = order.clone();
+            spawner.spawn(async move {
+                for _ in 0..3 {
+                    order.lock().unwrap().push(name);
+                    yield_now().await;
+                }
+            });
+        }
+        std::mem::drop(spawner);
+        executor.run();
+
+        assert_eq!(*order.lock().unwrap(), ['a', 'b', 'a', 'b', 'a', 'b']);
+    }
+
+    #[test]
+    fn an_always_ready_socket_does_not_starve_other_tasks() {
+        for mode in [DriverMode::ReactorThread, DriverMode::CurrentThread] {
+            let (executor, spawner) = Builder::new().mode(mode).build().unwrap();
+            let stop = Arc::new(AtomicBool::new(false));
+            let sink = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            let sink_addr = sink.local_addr().unwrap();
+
+            // sending never blocks, so this loop never sees `Pending` on its own
+            let flag = stop.clone();
+            spawner.spawn(async move {
+                let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+                while !flag.load(Ordering::SeqCst) {
+                    let _ = socket.send_to(b"flood", sink_addr).await;
+                }
+            });
+
+            let start = Instant::now();
+            executor.block_on(async move {
+                // needs both another turn on the executor and a timer firing
+                sleep(Duration::from_millis(10)).await;
+                stop.store(true, Ordering::SeqCst);
+            });
+
+            assert!(start.elapsed() < Duration::from_secs(2), "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn the_budget_is_only_enforced_inside_tasks() {
+        let waker = std::task::Waker::noop();
+        let mut cx = Context::from_waker(waker);
+
+        assert!((0..BUDGET * 2).all(|_| poll_proceed(&mut cx).is_ready()));
+
+        with_budget(|| {
+            assert!((0..BUDGET).all(|_| poll_proceed(&mut cx).is_ready()));
+            assert!(poll_proceed(&mut cx).is_pending());
+        });
+        assert!(poll_proceed(&mut cx).is_ready());
+    }
+}
@@ -0,0 +1,395 @@
+//! Graceful shutdown for servers.
+//!
+//! A server spawns its handlers through a `Drain`, and wraps whatever
+//! waits for new work (an accept, a read on an idle connection) in
+//! `until_shutdown`. `shutdown` then stops new work from starting, gives
+//! the handlers already running until a deadline to finish, and drops the
+//! ones still running after that at their next poll.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    pin::{pin, Pin},
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use super::{lock, time::timeout, Spawner};
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Tracks in-flight handlers; clones share the same state.
+#[derive(Clone, Default)]
+pub struct Drain {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    shutting_down: bool,
+    // past the deadline: handlers stop at their next poll
+    canceling: bool,
+    next_id: u64,
+    // the waker of each running handler, to cancel it
+    in_flight: HashMap<u64, Option<Waker>>,
+    // tasks waiting for shutdown to start, or for the last handler
+    waiters: HashMap<u64, Waker>,
+    completed: u64,
+    canceled: u64,
+    rejected: u64,
+}
+
+impl State {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn take_waiters(&mut self) -> Vec<Waker> {
+        self.waiters.drain().map(|(_, waker)| waker).collect()
+    }
+}
+
+/// What happened to the handlers during a shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrainReport {
+    /// Handlers that finished, before or during the shutdown.
+    pub completed: u64,
+    /// Handlers dropped after the deadline.
+    pub canceled: u64,
+    /// Work turned away because the shutdown had started.
+    pub rejected: u64,
+    /// How long the shutdown took.
+    pub elapsed: Duration,
+}
+
+impl DrainReport {
+    /// Requests that were cut off or never started.
+    pub fn dropped(&self) -> u64 {
+        self.canceled + self.rejected
+    }
+}
+
+impl fmt::Display for DrainReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} completed, {} canceled, {} rejected in {:.2?}",
+            self.completed, self.canceled, self.rejected, self.elapsed
+        )
+    }
+}
+
+impl Drain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        lock(&self.state).shutting_down
+    }
+
+    /// Handlers spawned and not yet finished.
+    pub fn in_flight(&self) -> usize {
+        lock(&self.state).in_flight.len()
+    }
+
+    /// Spawns `handler` as tracked work, unless the shutdown has started,
+    /// in which case it is dropped and counted as rejected.
+    pub fn spawn(
+        &self,
+        spawner: &Spawner,
+        handler: impl Future<Output = ()> + Send + 'static,
+    ) -> bool {
+        let mut state = lock(&self.state);
+        if state.shutting_down {
+            state.rejected += 1;
+            return false;
+        }
+        let id = state.next_id();
+        state.in_flight.insert(id, None);
+        std::mem::drop(state);
+
+        spawner.spawn(Tracked {
+            drain: self.clone(),
+            id,
+            future: Some(Box::pin(handler)),
+        });
+        true
+    }
+
+    /// Counts work turned away by the caller, e.g. a request that arrived
+    /// on a connection that is closing.
+    pub fn reject(&self) {
+        lock(&self.state).rejected += 1;
+    }
+
+    /// Runs `future` until it completes, or `None` once the shutdown starts.
+    ///
+    /// The shutdown is checked first, so a loop around an accept that is
+    /// always ready still ends.
+    pub async fn until_shutdown<F: Future>(&self, future: F) -> Option<F::Output> {
+        let mut future = pin!(future);
+        let mut shutdown = self.wait(|state| state.shutting_down);
+
+        std::future::poll_fn(|cx| {
+            if Pin::new(&mut shutdown).poll(cx).is_ready() {
+                return Poll::Ready(None);
+            }
+            future.as_mut().poll(cx).map(Some)
+        })
+        .await
+    }
+
+    /// Stops new work from starting, without waiting for anything.
+    pub fn begin(&self) {
+        let wakers = {
+            let mut state = lock(&self.state);
+            state.shutting_down = true;
+            state.take_waiters()
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+
+    /// Shuts down: stops new work, waits up to `grace` for the handlers in
+    /// flight, then cancels the rest.
+    pub async fn shutdown(&self, grace: Duration) -> DrainReport {
+        let start = Instant::now();
+        self.begin();
+
+        if timeout(grace, self.wait(|state| state.in_flight.is_empty()))
+            .await
+            .is_err()
+        {
+            let wakers: Vec<_> = {
+                let mut state = lock(&self.state);
+                state.canceling = true;
+                state.in_flight.values_mut().filter_map(Option::take).collect()
+            };
+            wakers.into_iter().for_each(Waker::wake);
+
+            self.wait(|state| state.in_flight.is_empty()).await;
+        }
+
+        let state = lock(&self.state);
+        DrainReport {
+            completed: state.completed,
+            canceled: state.canceled,
+            rejected: state.rejected,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    fn wait(&self, done: fn(&State) -> bool) -> Wait {
+        Wait {
+            drain: self.clone(),
+            id: None,
+            done,
+        }
+    }
+
+    /// Takes handler `id` off the books.
+    fn finish(&self, id: u64, completed: bool) {
+        let wakers = {
+            let mut state = lock(&self.state);
+            state.in_flight.remove(&id);
+            match completed {
+                true => state.completed += 1,
+                false => state.canceled += 1,
+            }
+            state.take_waiters()
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// Resolves once `done` holds.
+struct Wait {
+    drain: Drain,
+    id: Option<u64>,
+    done: fn(&State) -> bool,
+}
+
+impl Future for Wait {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = lock(&self.drain.state);
+        if (self.done)(&state) {
+            return Poll::Ready(());
+        }
+
+        let id = match self.id {
+            Some(id) => id,
+            None => state.next_id(),
+        };
+        state.waiters.insert(id, cx.waker().clone());
+        std::mem::drop(state);
+
+        self.id = Some(id);
+        Poll::Pending
+    }
+}
+
+impl Drop for Wait {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            lock(&self.drain.state).waiters.remove(&id);
+        }
+    }
+}
+
+/// A handler that can be canceled, and reports when it is done.
+struct Tracked {
+    drain: Drain,
+    id: u64,
+    // `None` once finished either way
+    future: Option<BoxFuture>,
+}
+
+impl Future for Tracked {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let canceling = {
+            let mut state = lock(&self.drain.state);
+            if !state.canceling {
+                state.in_flight.insert(self.id, Some(cx.waker().clone()));
+            }
+            state.canceling
+        };
+
+        let Some(future) = self.future.as_mut() else {
+            return Poll::Ready(());
+        };
+        let completed = match canceling {
+            true => false,
+            false => match future.as_mut().poll(cx) {
+                Poll::Ready(()) => true,
+                Poll::Pending => return Poll::Pending,
+            },
+        };
+
+        // drop the handler before anyone hears it is gone
+        self.future = None;
+        self.drain.finish(self.id, completed);
+        Poll::Ready(())
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        // the executor went away with the handler still pending
+        if self.future.take().is_some() {
+            self.drain.finish(self.id, false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::executor::{sleep, Builder, DriverMode};
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn shutdown_waits_for_handlers_that_finish_in_time() {
+        for mode in [DriverMode::ReactorThread, DriverMode::CurrentThread] {
+            let (executor, spawner) = Builder::new().mode(mode).build().unwrap();
+
+            let report = executor.block_on(async move {
+                let drain = Drain::new();
+                for delay in [5, 10, 20] {
+                    drain.spawn(&spawner, sleep(ms(delay)));
+                }
+                assert_eq!(drain.in_flight(), 3);
+
+                drain.shutdown(Duration::from_secs(5)).await
+            });
+
+            assert_eq!((report.completed, report.dropped()), (3, 0), "{mode:?}");
+            assert!(report.elapsed < Duration::from_secs(1), "{report}");
+        }
+    }
+
+    #[test]
+    fn handlers_past_the_deadline_are_dropped() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+        let dropped = Arc::new(AtomicBool::new(false));
+
+        struct SetOnDrop(Arc<AtomicBool>);
+
+        impl Drop for SetOnDrop {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let report = executor.block_on({
+            let dropped = dropped.clone();
+            async move {
+                let drain = Drain::new();
+                drain.spawn(&spawner, sleep(ms(1)));
+                drain.spawn(&spawner, async move {
+                    let _guard = SetOnDrop(dropped);
+                    sleep(Duration::from_secs(60)).await;
+                });
+
+                drain.shutdown(ms(30)).await
+            }
+        });
+
+        assert!(dropped.load(Ordering::SeqCst));
+        assert_eq!((report.completed, report.canceled), (1, 1));
+        assert!(report.elapsed >= ms(30), "{report}");
+    }
+
+    #[test]
+    fn work_after_the_shutdown_started_is_rejected() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let report = executor.block_on(async move {
+            let drain = Drain::new();
+            drain.begin();
+
+            assert!(!drain.spawn(&spawner, async {}));
+            drain.reject();
+            drain.shutdown(ms(10)).await
+        });
+
+        assert_eq!(report.rejected, 2);
+        assert_eq!(report.dropped(), 2);
+    }
+
+    #[test]
+    fn waiting_for_work_stops_when_the_shutdown_starts() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let (idle, ready) = executor.block_on(async move {
+            let drain = Drain::new();
+
+            let trigger = drain.clone();
+            spawner.spawn(async move {
+                sleep(ms(10)).await;
+                trigger.begin();
+            });
+
+            let idle = drain
+                .until_shutdown(std::future::pending::<()>())
+                .await;
+            let ready = drain.until_shutdown(async { 7 }).await;
+            (idle, ready)
+        });
+
+        assert_eq!(idle, None);
+        // nothing new starts, even if it could
+        assert_eq!(ready, None);
+    }
+}
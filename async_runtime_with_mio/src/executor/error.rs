@@ -0,0 +1,37 @@
+use std::{fmt, io};
+
+/// Why an executor could not be built.
+#[derive(Debug)]
+pub enum Error {
+    /// Creating the `mio::Poll`, or the waker that interrupts it, failed;
+    /// usually because the process ran out of file descriptors.
+    Reactor(io::Error),
+    /// The `reactor` thread could not be started.
+    Thread(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Reactor(error) => write!(f, "failed to create the reactor: {error}"),
+            Error::Thread(error) => write!(f, "failed to start the reactor thread: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Reactor(error) | Error::Thread(error) => Some(error),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        let kind = match &error {
+            Error::Reactor(inner) | Error::Thread(inner) => inner.kind(),
+        };
+        io::Error::new(kind, error)
+    }
+}
@@ -0,0 +1,206 @@
+//! Files, read and written at explicit offsets.
+//!
+//! `epoll` can't wait for a regular file, which is always "ready", so with
+//! the `Epoll` backend these calls run in place and block the executor
+//! thread for as long as the disk takes. With `IoUring` the kernel does the
+//! I/O in the background and wakes the task once it is done.
+
+use std::{
+    fs::OpenOptions,
+    io::{self, ErrorKind},
+    os::unix::fs::FileExt,
+    path::Path,
+};
+#[cfg(target_os = "linux")]
+use std::{os::fd::AsRawFd, sync::Arc};
+
+#[cfg(target_os = "linux")]
+use super::uring::{self, Uring};
+use super::Reactor;
+
+/// Chunk size of `read_to_end`.
+const READ_CHUNK: usize = 64 * 1024;
+
+/// A file doing its I/O through the current executor's `IoBackend`.
+pub struct File {
+    file: std::fs::File,
+    #[cfg(target_os = "linux")]
+    uring: Option<Arc<Uring>>,
+}
+
+impl File {
+    /// Opens the file at `path` for reading.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_std(std::fs::File::open(path)?)
+    }
+
+    /// Opens the file at `path` for writing, creating or truncating it.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_std(std::fs::File::create(path)?)
+    }
+
+    /// Opens the file at `path` with `options`.
+    pub fn open_with(path: impl AsRef<Path>, options: &OpenOptions) -> io::Result<Self> {
+        Self::from_std(options.open(path)?)
+    }
+
+    pub fn from_std(file: std::fs::File) -> io::Result<Self> {
+        #[cfg(target_os = "linux")]
+        let uring = Reactor::try_get()?.uring().cloned();
+        #[cfg(not(target_os = "linux"))]
+        Reactor::try_get()?;
+
+        Ok(File {
+            file,
+            #[cfg(target_os = "linux")]
+            uring,
+        })
+    }
+
+    pub fn get_ref(&self) -> &std::fs::File {
+        &self.file
+    }
+
+    pub fn into_std(self) -> std::fs::File {
+        self.file
+    }
+
+    /// Reads into `buf` from `offset`, returning how much was read; 0 at
+    /// the end of the file.
+    pub async fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        #[cfg(target_os = "linux")]
+        if let Some(uring) = &self.uring {
+            return uring::read_at(uring, self.file.as_raw_fd(), buf, offset).await;
+        }
+
+        self.file.read_at(buf, offset)
+    }
+
+    /// Writes from `buf` at `offset`, returning how much was written.
+    pub async fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        #[cfg(target_os = "linux")]
+        if let Some(uring) = &self.uring {
+            return uring::write_at(uring, self.file.as_raw_fd(), buf, offset).await;
+        }
+
+        self.file.write_at(buf, offset)
+    }
+
+    pub async fn write_all_at(&self, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write_at(buf, offset).await? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                n => {
+                    buf = &buf[n..];
+                    offset += n as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the whole file, from its start, to `buf`, returning how
+    /// much was read.
+    pub async fn read_to_end(&self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let start = buf.len();
+        loop {
+            let len = buf.len();
+            buf.resize(len + READ_CHUNK, 0);
+            let result = self.read_at(&mut buf[len..], (len - start) as u64).await;
+
+            match result {
+                Ok(0) => {
+                    buf.truncate(len);
+                    return Ok(len - start);
+                }
+                Ok(n) => buf.truncate(len + n),
+                Err(error) => {
+                    buf.truncate(len);
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{Builder, DriverMode, IoBackend};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{name}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn files_round_trip_on_both_backends() {
+        for backend in [IoBackend::Epoll, IoBackend::IoUring] {
+            for mode in [DriverMode::ReactorThread, DriverMode::CurrentThread] {
+                let (executor, _spawner) = Builder::new()
+                    .mode(mode)
+                    .io_backend(backend)
+                    .build()
+                    .unwrap();
+                let path = temp_path(&format!("fs-{backend:?}-{mode:?}"));
+
+                // more than one chunk, and not a multiple of it
+                let data: Vec<u8> = (0..READ_CHUNK * 2 + 123).map(|i| i as u8).collect();
+                let read = executor.block_on({
+                    let path = path.clone();
+                    let data = data.clone();
+                    async move {
+                        let file = File::create(&path).unwrap();
+                        file.write_all_at(&data, 0).await.unwrap();
+                        file.write_all_at(b"patched", 10).await.unwrap();
+
+                        let file = File::open(&path).unwrap();
+                        let mut read = b"prefix".to_vec();
+                        let n = file.read_to_end(&mut read).await.unwrap();
+                        assert_eq!(n, data.len());
+                        read
+                    }
+                });
+                std::fs::remove_file(&path).unwrap();
+
+                let mut expected = b"prefix".to_vec();
+                expected.extend_from_slice(&data);
+                expected[6 + 10..6 + 17].copy_from_slice(b"patched");
+                assert!(read == expected, "{backend:?} {mode:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn reading_past_the_end_returns_nothing() {
+        let (executor, _spawner) = Builder::new()
+            .current_thread()
+            .io_backend(IoBackend::IoUring)
+            .build()
+            .unwrap();
+
+        executor.block_on(async {
+            let file = File::open("Cargo.toml").unwrap();
+            let len = file.get_ref().metadata().unwrap().len();
+
+            let mut buf = [0; 16];
+            assert_eq!(file.read_at(&mut buf, len).await.unwrap(), 0);
+            assert_eq!(file.read_at(&mut buf, 0).await.unwrap(), 16);
+            assert_eq!(&buf[..9], b"[package]");
+        });
+    }
+
+    #[test]
+    fn writing_a_read_only_file_fails() {
+        let (executor, _spawner) = Builder::new()
+            .current_thread()
+            .io_backend(IoBackend::IoUring)
+            .build()
+            .unwrap();
+
+        executor.block_on(async {
+            let file = File::open("Cargo.toml").unwrap();
+            let error = file.write_at(b"x", 0).await.unwrap_err();
+            assert_eq!(error.raw_os_error(), Some(libc::EBADF));
+        });
+    }
+}
@@ -0,0 +1,203 @@
+//! Tasks that are not `Send`.
+//!
+//! A local future never leaves the thread that spawned it. It is kept in a
+//! thread-local table, and the ready queue only holds a `Send` stand-in
+//! that looks it up there when polled. Wakers stay `Send` as usual, so a
+//! local task can still be woken by the reactor thread.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    thread::{self, ThreadId},
+};
+
+use super::{trace::TaskMeta, Priority, Shared, Spawner};
+
+type LocalFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static TASKS: RefCell<HashMap<u64, LocalFuture>> = RefCell::new(HashMap::new());
+    // the ready queue of the executor running on this thread
+    static RUNNING: RefCell<Option<Arc<Shared>>> = const { RefCell::new(None) };
+}
+
+/// Marks the current thread as running the executor behind `shared`
+/// until the guard is dropped.
+pub(crate) fn enter(shared: Arc<Shared>) -> impl Drop {
+    struct Exit(Option<Arc<Shared>>);
+
+    impl Drop for Exit {
+        fn drop(&mut self) {
+            RUNNING.with(|running| *running.borrow_mut() = self.0.take());
+        }
+    }
+
+    Exit(RUNNING.with(|running| running.borrow_mut().replace(shared)))
+}
+
+/// Spawns a `!Send` future on the executor running on this thread.
+///
+/// # Panics
+///
+/// If called outside of a task or `block_on`, since no executor would be
+/// pinned to this thread to poll the future.
+pub fn spawn_local(future: impl Future<Output = ()> + 'static) {
+    let shared = RUNNING
+        .with(|running| running.borrow().clone())
+        .expect("`spawn_local` called outside of an executor thread");
+
+    let name = std::any::type_name_of_val(&future);
+    spawn(&Spawner::new(shared), name, future);
+}
+
+/// Spawns a local future through `spawner`, owned by the current thread.
+pub(crate) fn spawn(
+    spawner: &Spawner,
+    name: &'static str,
+    future: impl Future<Output = ()> + 'static,
+) {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    TASKS.with(|tasks| tasks.borrow_mut().insert(id, Box::pin(future)));
+
+    let local = Local {
+        id,
+        owner: thread::current().id(),
+    };
+    spawner.spawn_with_meta(Priority::Normal, TaskMeta::new(name), local);
+}
+
+/// The `Send` stand-in for a local future.
+struct Local {
+    id: u64,
+    owner: ThreadId,
+}
+
+impl Future for Local {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        assert_eq!(
+            thread::current().id(),
+            self.owner,
+            "a local task was polled off the thread that spawned it"
+        );
+
+        // taken out while it runs, so that it can spawn more local tasks
+        let Some(mut future) = TASKS.with(|tasks| tasks.borrow_mut().remove(&self.id)) else {
+            return Poll::Ready(());
+        };
+
+        match future.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(()),
+            Poll::Pending => {
+                TASKS.with(|tasks| tasks.borrow_mut().insert(self.id, future));
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        // Elsewhere the future can't be reached, nor safely dropped; it is
+        // freed with its thread.
+        if thread::current().id() == self.owner {
+            let future = TASKS
+                .try_with(|tasks| tasks.borrow_mut().remove(&self.id))
+                .ok()
+                .flatten();
+            std::mem::drop(future);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{rc::Rc, time::Duration};
+
+    use super::*;
+    use crate::executor::{sleep, yield_now, Builder, DriverMode};
+
+    #[test]
+    fn local_tasks_share_rc_state() {
+        for mode in [DriverMode::ReactorThread, DriverMode::CurrentThread] {
+            let (executor, _spawner) = Builder::new().mode(mode).build().unwrap();
+
+            let log = executor.block_on_local(async {
+                let log = Rc::new(RefCell::new(Vec::new()));
+
+                for name in ['a', 'b'] {
+                    let log = log.clone();
+                    spawn_local(async move {
+                        log.borrow_mut().push(name);
+                        // woken by the reactor, possibly on its own thread
+                        sleep(Duration::from_millis(5)).await;
+                        log.borrow_mut().push(name);
+                    });
+                }
+
+                sleep(Duration::from_millis(30)).await;
+                Rc::try_unwrap(log).unwrap().into_inner()
+            });
+
+            assert_eq!(log, ['a', 'b', 'a', 'b'], "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn send_tasks_can_spawn_local_ones() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        let count = executor.block_on(async {
+            let (sender, receiver) = crate::executor::sync::oneshot::channel();
+
+            spawn_local(async move {
+                let count = Rc::new(RefCell::new(0));
+                for _ in 0..3 {
+                    *count.borrow_mut() += 1;
+                    yield_now().await;
+                }
+                let _ = sender.send(*count.borrow());
+            });
+            receiver.await.unwrap()
+        });
+
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn dropping_the_executor_frees_pending_local_tasks() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+        let state = Rc::new(());
+
+        let held = state.clone();
+        executor.block_on_local(async move {
+            spawn_local(async move {
+                let _held = held;
+                sleep(Duration::from_secs(60)).await;
+            });
+            yield_now().await;
+        });
+        assert_eq!(Rc::strong_count(&state), 2);
+
+        std::mem::drop(executor);
+        assert_eq!(Rc::strong_count(&state), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside of an executor thread")]
+    fn spawn_local_needs_a_running_executor() {
+        let (_executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        spawn_local(async {});
+    }
+}
@@ -0,0 +1,488 @@
+#[cfg(target_os = "linux")]
+use std::os::fd::AsRawFd;
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, ToSocketAddrs},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use mio::Interest;
+
+use super::{
+    reactor::{Direction, Registration},
+    stream::Stream,
+};
+use crate::codec::MAX_DATAGRAM_SIZE;
+
+// async udpsocket
+pub struct UdpSocket {
+    // before `socket`, so they are canceled while it is still open
+    #[cfg(target_os = "linux")]
+    parked: super::uring::ParkedRecvs,
+    socket: mio::net::UdpSocket,
+    registration: Registration,
+}
+
+impl UdpSocket {
+    pub fn bind(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let std_socket = std::net::UdpSocket::bind(addr)?;
+        std_socket.set_nonblocking(true)?;
+
+        let mut socket = mio::net::UdpSocket::from_std(std_socket);
+
+        let registration = Registration::new(&mut socket, Interest::READABLE | Interest::WRITABLE)?;
+
+        Ok(self::UdpSocket {
+            #[cfg(target_os = "linux")]
+            parked: Default::default(),
+            socket,
+            registration,
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+impl UdpSocket {
+    pub async fn send_to(&self, buf: &[u8], dest: SocketAddr) -> std::io::Result<usize> {
+        #[cfg(target_os = "linux")]
+        if let Some(uring) = self.registration.uring() {
+            return super::uring::send_to(uring, self.socket.as_raw_fd(), buf, dest).await;
+        }
+
+        self.registration
+            .async_io(Direction::Write, || self.socket.send_to(buf, dest))
+            .await
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        let _ = self.registration.deregister(&mut self.socket);
+    }
+}
+
+impl UdpSocket {
+    pub async fn recv_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+        #[cfg(target_os = "linux")]
+        if let Some(uring) = self.registration.uring() {
+            let fd = self.socket.as_raw_fd();
+            return super::uring::recv_from(uring, fd, buf, &self.parked).await;
+        }
+
+        self.registration
+            .async_io(Direction::Read, || self.socket.recv_from(buf))
+            .await
+    }
+
+    /// `recv_from` for hand-written futures and streams.
+    ///
+    /// This always waits for readiness, whatever the `IoBackend`.
+    pub fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<(usize, SocketAddr)>> {
+        loop {
+            let tick = ready!(self.registration.poll_ready(Direction::Read, cx))?;
+
+            match self.socket.recv_from(buf) {
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    self.registration.clear_ready(Direction::Read, tick)
+                }
+                result => return Poll::Ready(self.registration.report(Direction::Read, result)),
+            }
+        }
+    }
+
+    /// A never-ending stream of the datagrams arriving on this socket.
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming {
+            socket: self,
+            buf: vec![0; MAX_DATAGRAM_SIZE],
+        }
+    }
+}
+
+pub struct Incoming<'a> {
+    socket: &'a UdpSocket,
+    buf: Vec<u8>,
+}
+
+impl Stream for Incoming<'_> {
+    type Item = std::io::Result<(Vec<u8>, SocketAddr)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let Incoming { socket, buf } = &mut *self;
+
+        let received = ready!(socket.poll_recv_from(cx, buf));
+        Poll::Ready(Some(
+            received.map(|(amt, from)| (buf[..amt].to_vec(), from)),
+        ))
+    }
+}
+
+// async tcp
+pub struct TcpListener {
+    listener: mio::net::TcpListener,
+    registration: Registration,
+}
+
+impl TcpListener {
+    pub fn bind(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let std_listener = std::net::TcpListener::bind(addr)?;
+        std_listener.set_nonblocking(true)?;
+
+        let mut listener = mio::net::TcpListener::from_std(std_listener);
+
+        let registration = Registration::new(&mut listener, Interest::READABLE)?;
+
+        Ok(TcpListener {
+            listener,
+            registration,
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub async fn accept(&self) -> std::io::Result<(TcpStream, SocketAddr)> {
+        let (stream, addr) = self
+            .registration
+            .async_io(Direction::Read, || self.listener.accept())
+            .await?;
+
+        Ok((TcpStream::new(stream)?, addr))
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        let _ = self.registration.deregister(&mut self.listener);
+    }
+}
+
+pub struct TcpStream {
+    stream: mio::net::TcpStream,
+    registration: Registration,
+}
+
+impl TcpStream {
+    fn new(mut stream: mio::net::TcpStream) -> std::io::Result<Self> {
+        let registration = Registration::new(&mut stream, Interest::READABLE | Interest::WRITABLE)?;
+
+        Ok(TcpStream {
+            stream,
+            registration,
+        })
+    }
+
+    pub async fn connect(addr: SocketAddr) -> std::io::Result<Self> {
+        let stream = TcpStream::new(mio::net::TcpStream::connect(addr)?)?;
+
+        // A non-blocking connect is done once the socket turns writable:
+        // then it either has a peer or an error to report.
+        stream
+            .io(Direction::Write, |stream| {
+                if let Some(error) = stream.take_error()? {
+                    return Err(error);
+                }
+                match stream.peer_addr() {
+                    Ok(_) => Ok(()),
+                    Err(error) if error.kind() == ErrorKind::NotConnected => {
+                        Err(ErrorKind::WouldBlock.into())
+                    }
+                    Err(error) => Err(error),
+                }
+            })
+            .await?;
+
+        Ok(stream)
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.stream.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    pub fn set_nodelay(&self, nodelay: bool) -> std::io::Result<()> {
+        self.stream.set_nodelay(nodelay)
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> std::io::Result<()> {
+        self.stream.shutdown(how)
+    }
+
+    /// Runs a non-blocking operation on the inner mio stream, sleeping
+    /// until `direction` is ready whenever it would block.
+    pub(crate) async fn io<R>(
+        &self,
+        direction: Direction,
+        mut f: impl FnMut(&mio::net::TcpStream) -> std::io::Result<R>,
+    ) -> std::io::Result<R> {
+        self.registration
+            .async_io(direction, || f(&self.stream))
+            .await
+    }
+}
+
+impl TcpStream {
+    /// Reads into `buf`, returning 0 once the peer has closed its side.
+    pub async fn read(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.io(Direction::Read, |mut stream| stream.read(buf))
+            .await
+    }
+
+    pub async fn read_exact(&self, mut buf: &mut [u8]) -> std::io::Result<()> {
+        while !buf.is_empty() {
+            match self.read(buf).await? {
+                0 => return Err(ErrorKind::UnexpectedEof.into()),
+                n => buf = &mut buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn write(&self, buf: &[u8]) -> std::io::Result<usize> {
+        self.io(Direction::Write, |mut stream| stream.write(buf))
+            .await
+    }
+
+    pub async fn write_all(&self, mut buf: &[u8]) -> std::io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        let _ = self.registration.deregister(&mut self.stream);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread, time::Duration};
+
+    use super::*;
+    use crate::executor::{time::timeout, Builder, DriverMode, Spawner};
+
+    const CLIENTS: usize = 16;
+    const REQUESTS: usize = 200;
+
+    fn spawn_reverse_echo(spawner: &Spawner, server: UdpSocket) {
+        spawner.spawn(async move {
+            let mut buf = [0; 64];
+            loop {
+                let (amt, src) = server.recv_from(&mut buf).await.unwrap();
+                buf[..amt].reverse();
+                server.send_to(&buf[..amt], src).await.unwrap();
+            }
+        });
+    }
+
+    /// Waits until `count` notifications have arrived on `done`.
+    async fn wait_for(done: &UdpSocket, count: usize) {
+        let mut buf = [0; 1];
+        for _ in 0..count {
+            done.recv_from(&mut buf).await.unwrap();
+        }
+    }
+
+    /// Joins `threads`, failing with the first one's panic, once the
+    /// executor is done waiting for them.
+    fn join_all(threads: Vec<thread::JoinHandle<()>>) {
+        for thread in threads {
+            if let Err(panic) = thread.join() {
+                std::panic::resume_unwind(panic);
+            }
+        }
+    }
+
+    fn hammer_from_threads(mode: DriverMode) {
+        let (executor, spawner) = Builder::new().mode(mode).build().unwrap();
+
+        let (finished, clients) = executor.block_on(async move {
+            let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let server_addr = server.local_addr().unwrap();
+            spawn_reverse_echo(&spawner, server);
+
+            let done = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let done_addr = done.local_addr().unwrap();
+
+            let mut clients = Vec::new();
+            for client in 0..CLIENTS {
+                clients.push(thread::spawn(move || {
+                    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+                    socket
+                        .set_read_timeout(Some(Duration::from_secs(5)))
+                        .unwrap();
+
+                    let mut buf = [0; 64];
+                    for request in 0..REQUESTS {
+                        let message = format!("{client}:{request}");
+                        socket.send_to(message.as_bytes(), server_addr).unwrap();
+
+                        let (amt, _) = socket.recv_from(&mut buf).unwrap();
+                        let expected: Vec<u8> = message.bytes().rev().collect();
+                        assert_eq!(&buf[..amt], expected);
+                    }
+                    socket.send_to(&[1], done_addr).unwrap();
+                }));
+            }
+
+            // a client that panics never reports, so don't wait forever
+            let finished = timeout(Duration::from_secs(30), wait_for(&done, CLIENTS)).await;
+            (finished, clients)
+        });
+
+        join_all(clients);
+        finished.expect("clients still running");
+    }
+
+    #[test]
+    fn many_blocking_clients_against_reactor_thread_driver() {
+        hammer_from_threads(DriverMode::ReactorThread);
+    }
+
+    #[test]
+    fn many_blocking_clients_against_current_thread_driver() {
+        hammer_from_threads(DriverMode::CurrentThread);
+    }
+
+    fn hammer_from_tasks(mode: DriverMode) {
+        let (executor, spawner) = Builder::new().mode(mode).build().unwrap();
+
+        executor.block_on(async move {
+            let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let server_addr = server.local_addr().unwrap();
+            spawn_reverse_echo(&spawner, server);
+
+            let done = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let done_addr = done.local_addr().unwrap();
+
+            for client in 0..CLIENTS {
+                spawner.spawn(async move {
+                    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+                    let mut buf = [0; 64];
+                    for request in 0..REQUESTS {
+                        let message = format!("{client}:{request}");
+                        socket
+                            .send_to(message.as_bytes(), server_addr)
+                            .await
+                            .unwrap();
+
+                        let (amt, _) = socket.recv_from(&mut buf).await.unwrap();
+                        let expected: Vec<u8> = message.bytes().rev().collect();
+                        assert_eq!(&buf[..amt], expected);
+                    }
+                    socket.send_to(&[1], done_addr).await.unwrap();
+                });
+            }
+
+            wait_for(&done, CLIENTS).await;
+        });
+    }
+
+    #[test]
+    fn many_client_tasks_share_the_executor_with_the_server() {
+        hammer_from_tasks(DriverMode::ReactorThread);
+        hammer_from_tasks(DriverMode::CurrentThread);
+    }
+
+    #[test]
+    fn reader_and_writer_tasks_wait_on_the_same_socket() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let (finished, peer) = executor.block_on(async move {
+            let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+            let socket_addr = socket.local_addr().unwrap();
+            let done = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let done_addr = done.local_addr().unwrap();
+
+            let peer = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            let peer_addr = peer.local_addr().unwrap();
+            peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let peer = thread::spawn(move || {
+                let mut buf = [0; 8];
+                let (amt, _) = peer.recv_from(&mut buf).unwrap();
+                peer.send_to(&buf[..amt], socket_addr).unwrap();
+            });
+
+            // the reader parks first; the writer must not take over its waker
+            let reader = socket.clone();
+            spawner.spawn(async move {
+                let mut buf = [0; 8];
+                let (amt, _) = reader.recv_from(&mut buf).await.unwrap();
+                assert_eq!(&buf[..amt], b"ping");
+                reader.send_to(&[1], done_addr).await.unwrap();
+            });
+
+            socket.send_to(b"ping", peer_addr).await.unwrap();
+            let finished = timeout(Duration::from_secs(5), wait_for(&done, 1)).await;
+            (finished, peer)
+        });
+
+        join_all(vec![peer]);
+        finished.expect("the reader never got the echo");
+    }
+
+    #[test]
+    fn tcp_stream_round_trips_through_a_listener() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let reply = executor.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            spawner.spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 5];
+                stream.read_exact(&mut buf).await.unwrap();
+                buf.reverse();
+                stream.write_all(&buf).await.unwrap();
+                stream.shutdown(Shutdown::Write).unwrap();
+            });
+
+            let stream = TcpStream::connect(addr).await.unwrap();
+            assert_eq!(stream.peer_addr().unwrap(), addr);
+            stream.write_all(b"hello").await.unwrap();
+
+            let mut reply = Vec::new();
+            let mut buf = [0; 8];
+            loop {
+                match stream.read(&mut buf).await.unwrap() {
+                    0 => break reply,
+                    n => reply.extend_from_slice(&buf[..n]),
+                }
+            }
+        });
+
+        assert_eq!(reply, b"olleh");
+    }
+
+    #[test]
+    fn tcp_connect_reports_a_refused_connection() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        let addr = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+
+        let result = executor.block_on(async move { TcpStream::connect(addr).await.map(drop) });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::ConnectionRefused);
+    }
+}
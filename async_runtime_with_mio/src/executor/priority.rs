@@ -0,0 +1,146 @@
+//! Task priorities.
+//!
+//! Ready tasks wait in one FIFO per priority. The executor takes them in a
+//! fixed weighted cycle, so under load high-priority tasks get most of the
+//! polls, but normal and background tasks still get their share instead of
+//! waiting for the busier classes to go idle.
+
+use std::{collections::VecDeque, sync::Arc};
+
+use super::Task;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Latency-sensitive control work, such as heartbeats.
+    High,
+    #[default]
+    Normal,
+    /// Bulk work that only needs to make progress eventually.
+    Background,
+}
+
+impl Priority {
+    const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Background];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Which class gets each turn while all of them have tasks ready: high
+/// gets 4 turns in 7, normal 2 and background 1.
+const CYCLE: [Priority; 7] = [
+    Priority::High,
+    Priority::Normal,
+    Priority::High,
+    Priority::Background,
+    Priority::High,
+    Priority::Normal,
+    Priority::High,
+];
+
+#[derive(Default)]
+pub(crate) struct ReadyQueue {
+    classes: [VecDeque<Arc<Task>>; 3],
+    turn: usize,
+}
+
+impl ReadyQueue {
+    pub(crate) fn push(&mut self, task: Arc<Task>) {
+        self.classes[task.priority.index()].push_back(task);
+    }
+
+    /// The next task to poll.
+    ///
+    /// A turn whose class has nothing ready goes to the highest priority
+    /// that does, so no turn is wasted.
+    pub(crate) fn pop(&mut self) -> Option<Arc<Task>> {
+        let preferred = CYCLE[self.turn];
+        self.turn = (self.turn + 1) % CYCLE.len();
+
+        std::iter::once(preferred)
+            .chain(Priority::ALL)
+            .find_map(|priority| self.classes[priority.index()].pop_front())
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.classes.iter().all(VecDeque::is_empty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    };
+
+    use super::*;
+    use crate::executor::{sync::oneshot, yield_now, Builder, DriverMode};
+
+    #[test]
+    fn busy_classes_share_turns_by_weight() {
+        for mode in [DriverMode::CurrentThread, DriverMode::ReactorThread] {
+            let (executor, spawner) = Builder::new().mode(mode).build().unwrap();
+            let order = Arc::new(Mutex::new(Vec::new()));
+
+            // spawned lowest first, so plain FIFO order would be wrong
+            for priority in Priority::ALL.into_iter().rev() {
+                let order = order.clone();
+                spawner.spawn_with_priority(priority, async move {
+                    for _ in 0..8 {
+                        order.lock().unwrap().push(priority);
+                        yield_now().await;
+                    }
+                });
+            }
+            std::mem::drop(spawner);
+            executor.run();
+
+            let order = order.lock().unwrap();
+            let first = &order[..CYCLE.len()];
+            let count = |priority| first.iter().filter(|&&p| p == priority).count();
+            assert_eq!(first[0], Priority::High, "{mode:?}");
+            assert_eq!(
+                (
+                    count(Priority::High),
+                    count(Priority::Normal),
+                    count(Priority::Background)
+                ),
+                (4, 2, 1),
+                "{mode:?}: {order:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn background_tasks_are_not_starved() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        // high-priority tasks that are always ready
+        for _ in 0..4 {
+            let stop = stop.clone();
+            spawner.spawn_with_priority(Priority::High, async move {
+                while !stop.load(Ordering::SeqCst) {
+                    yield_now().await;
+                }
+            });
+        }
+
+        let finished = executor.block_on(async move {
+            let (sender, receiver) = oneshot::channel();
+            spawner.spawn_with_priority(Priority::Background, async move {
+                for _ in 0..100 {
+                    yield_now().await;
+                }
+                let _ = sender.send(());
+            });
+            let finished = receiver.await.is_ok();
+            stop.store(true, Ordering::SeqCst);
+            finished
+        });
+
+        assert!(finished);
+    }
+}
@@ -0,0 +1,333 @@
+//! Token-bucket and leaky-bucket rate limiters.
+//!
+//! The limiters take the current time as an argument instead of reading
+//! the clock, so callers decide how often to look at it and tests can run
+//! on made-up instants.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// Allows bursts of up to `capacity` events, refilled at a steady rate.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    per_second: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket holding `capacity` tokens, refilling `per_second`.
+    /// A rate that isn't above zero, e.g. NaN, never refills.
+    pub fn new(capacity: u32, per_second: f64, now: Instant) -> Self {
+        TokenBucket {
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            per_second: valid_rate(per_second),
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.last = self.last.max(now);
+    }
+
+    /// Takes a token if one is left.
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// How long until the next token is available.
+    pub fn wait_time(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        match self.tokens >= 1.0 {
+            true => Duration::ZERO,
+            false => wait_for((1.0 - self.tokens) / self.per_second),
+        }
+    }
+}
+
+/// Lets events through at a steady rate, absorbing up to `capacity` of
+/// them at once; anything beyond overflows.
+///
+/// Unlike a token bucket, which starts full and allows a burst right away,
+/// a leaky bucket starts empty and its level is how far ahead of the rate
+/// the source currently is.
+#[derive(Debug, Clone)]
+pub struct LeakyBucket {
+    capacity: f64,
+    level: f64,
+    per_second: f64,
+    last: Instant,
+}
+
+impl LeakyBucket {
+    /// An empty bucket; a rate that isn't above zero never drains.
+    pub fn new(capacity: u32, per_second: f64, now: Instant) -> Self {
+        LeakyBucket {
+            capacity: capacity as f64,
+            level: 0.0,
+            per_second: valid_rate(per_second),
+            last: now,
+        }
+    }
+
+    fn leak(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.level = (self.level - elapsed * self.per_second).max(0.0);
+        self.last = self.last.max(now);
+    }
+
+    /// Adds an event to the bucket unless it would overflow.
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        self.leak(now);
+        if self.level + 1.0 <= self.capacity {
+            self.level += 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// How long until there is room for another event.
+    pub fn wait_time(&mut self, now: Instant) -> Duration {
+        self.leak(now);
+        let excess = self.level + 1.0 - self.capacity;
+        match excess > 0.0 {
+            true => wait_for(excess / self.per_second),
+            false => Duration::ZERO,
+        }
+    }
+}
+
+// negative and NaN rates would turn the levels into nonsense
+fn valid_rate(per_second: f64) -> f64 {
+    match per_second > 0.0 {
+        true => per_second,
+        false => 0.0,
+    }
+}
+
+/// `secs` as a wait, `Duration::MAX` for a bucket that never moves.
+fn wait_for(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+}
+
+/// Which limiter each source gets, if any.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimit {
+    Unlimited,
+    TokenBucket { burst: u32, per_second: f64 },
+    LeakyBucket { capacity: u32, per_second: f64 },
+}
+
+#[derive(Debug, Clone)]
+enum Bucket {
+    Token(TokenBucket),
+    Leaky(LeakyBucket),
+}
+
+impl Bucket {
+    fn new(limit: RateLimit, now: Instant) -> Option<Self> {
+        match limit {
+            RateLimit::Unlimited => None,
+            RateLimit::TokenBucket { burst, per_second } => {
+                Some(Bucket::Token(TokenBucket::new(burst, per_second, now)))
+            }
+            RateLimit::LeakyBucket {
+                capacity,
+                per_second,
+            } => Some(Bucket::Leaky(LeakyBucket::new(capacity, per_second, now))),
+        }
+    }
+
+    fn try_acquire(&mut self, now: Instant) -> bool {
+        match self {
+            Bucket::Token(bucket) => bucket.try_acquire(now),
+            Bucket::Leaky(bucket) => bucket.try_acquire(now),
+        }
+    }
+}
+
+/// One bucket per key, e.g. per source address, keeping at most
+/// `max_keys` buckets.
+///
+/// When full, the least recently seen key loses its bucket. That key
+/// starts over with a fresh one, so the bound should comfortably exceed
+/// the number of sources active at once.
+pub struct KeyedRateLimiter<K> {
+    limit: RateLimit,
+    max_keys: usize,
+    buckets: HashMap<K, (Bucket, u64)>,
+    /// Keys by last use, oldest first.
+    recency: BTreeMap<u64, K>,
+    clock: u64,
+}
+
+impl<K: Hash + Eq + Clone> KeyedRateLimiter<K> {
+    pub fn new(limit: RateLimit, max_keys: usize) -> Self {
+        KeyedRateLimiter {
+            limit,
+            max_keys: max_keys.max(1),
+            buckets: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// How many keys currently have a bucket.
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    /// Whether an event from `key` is allowed through at `now`.
+    pub fn check(&mut self, key: &K, now: Instant) -> bool {
+        self.clock += 1;
+        let clock = self.clock;
+
+        if let Some((bucket, last_used)) = self.buckets.get_mut(key) {
+            self.recency.remove(last_used);
+            self.recency.insert(clock, key.clone());
+            *last_used = clock;
+            return bucket.try_acquire(now);
+        }
+
+        let Some(mut bucket) = Bucket::new(self.limit, now) else {
+            return true;
+        };
+        if self.buckets.len() == self.max_keys {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.buckets.remove(&oldest);
+            }
+        }
+
+        let allowed = bucket.try_acquire(now);
+        self.buckets.insert(key.clone(), (bucket, clock));
+        self.recency.insert(clock, key.clone());
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn token_bucket_allows_a_burst_then_the_refill_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(3, 10.0, start);
+
+        assert!((0..3).all(|_| bucket.try_acquire(start)));
+        assert!(!bucket.try_acquire(start));
+        assert_eq!(bucket.wait_time(start), ms(100));
+
+        assert!(!bucket.try_acquire(start + ms(50)));
+        assert!(bucket.try_acquire(start + ms(100)));
+
+        // a long pause refills to the capacity, no further
+        let later = start + Duration::from_secs(10);
+        assert_eq!((0..10).filter(|_| bucket.try_acquire(later)).count(), 3);
+    }
+
+    #[test]
+    fn leaky_bucket_drains_at_a_steady_rate() {
+        let start = Instant::now();
+        let mut bucket = LeakyBucket::new(2, 4.0, start);
+
+        assert!(bucket.try_acquire(start));
+        assert!(bucket.try_acquire(start));
+        assert!(!bucket.try_acquire(start));
+        assert_eq!(bucket.wait_time(start), ms(250));
+
+        assert!(bucket.try_acquire(start + ms(250)));
+        assert!(!bucket.try_acquire(start + ms(300)));
+        assert!(bucket.try_acquire(start + ms(500)));
+    }
+
+    #[test]
+    fn buckets_without_a_rate_never_move() {
+        let start = Instant::now();
+        let later = start + Duration::from_secs(3600);
+
+        for per_second in [0.0, -1.0, f64::NAN] {
+            let mut bucket = TokenBucket::new(1, per_second, start);
+            assert!(bucket.try_acquire(start));
+            assert!(!bucket.try_acquire(later));
+            assert_eq!(bucket.wait_time(later), Duration::MAX);
+
+            let mut bucket = LeakyBucket::new(1, per_second, start);
+            assert!(bucket.try_acquire(start));
+            assert!(!bucket.try_acquire(later));
+            assert_eq!(bucket.wait_time(later), Duration::MAX);
+        }
+    }
+
+    #[test]
+    fn sources_are_limited_independently() {
+        let now = Instant::now();
+        let limit = RateLimit::TokenBucket {
+            burst: 2,
+            per_second: 1.0,
+        };
+        let mut limiter = KeyedRateLimiter::new(limit, 16);
+
+        assert!(limiter.check(&"a", now));
+        assert!(limiter.check(&"a", now));
+        assert!(!limiter.check(&"a", now));
+        assert!(limiter.check(&"b", now));
+        assert_eq!(limiter.len(), 2);
+    }
+
+    #[test]
+    fn the_least_recently_seen_source_is_evicted() {
+        let now = Instant::now();
+        let limit = RateLimit::LeakyBucket {
+            capacity: 1,
+            per_second: 1.0,
+        };
+        let mut limiter = KeyedRateLimiter::new(limit, 2);
+
+        assert!(limiter.check(&1, now));
+        assert!(limiter.check(&2, now));
+        // touching 1 makes 2 the oldest
+        assert!(!limiter.check(&1, now));
+        assert!(limiter.check(&3, now));
+        assert_eq!(limiter.len(), 2);
+
+        // 1 kept its full bucket, 2 was forgotten and starts over
+        assert!(!limiter.check(&1, now));
+        assert!(limiter.check(&2, now));
+    }
+
+    #[test]
+    fn unlimited_keeps_no_state() {
+        let now = Instant::now();
+        let mut limiter = KeyedRateLimiter::new(RateLimit::Unlimited, 4);
+
+        assert!((0..1000).all(|i| limiter.check(&(i % 10), now)));
+        assert!(limiter.is_empty());
+    }
+}
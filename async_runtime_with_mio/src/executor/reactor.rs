@@ -0,0 +1,869 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use mio::{event::Source, Interest, Registry, Token};
+
+#[cfg(target_os = "linux")]
+use super::uring::Uring;
+use super::{lock, trace::event, IoBackend};
+
+// Reserved for the mio::Waker that interrupts a blocked `mio::Poll`.
+const WAKE_TOKEN: Token = Token(usize::MAX);
+// Reserved for the io_uring descriptor, readable once completions are posted.
+#[cfg(target_os = "linux")]
+const URING_TOKEN: Token = Token(usize::MAX - 1);
+
+/// How long a failed reactor sleeps at most per turn, since nothing can
+/// interrupt it anymore; it still runs the timers.
+const FAILED_TICK: Duration = Duration::from_millis(10);
+
+// Begin Implementing the Reactor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Read,
+    Write,
+}
+
+/// The readiness state of one registered source.
+///
+/// `tick` counts the events delivered for the source. A task snapshots it
+/// together with the readiness it acts on, and may only clear that
+/// readiness while the tick is unchanged, so an event that arrives between
+/// a `WouldBlock` and the clear is never swallowed.
+#[derive(Default)]
+pub(crate) struct ScheduledIo {
+    tick: usize,
+    readable: bool,
+    writable: bool,
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+}
+
+impl ScheduledIo {
+    fn ready(&mut self, direction: Direction) -> &mut bool {
+        match direction {
+            Direction::Read => &mut self.readable,
+            Direction::Write => &mut self.writable,
+        }
+    }
+
+    fn waker(&mut self, direction: Direction) -> &mut Option<Waker> {
+        match direction {
+            Direction::Read => &mut self.reader,
+            Direction::Write => &mut self.writer,
+        }
+    }
+}
+
+pub struct Reactor {
+    registry: Registry,
+    registrations: Mutex<Slab>,
+    timers: Mutex<Timers>,
+    unparker: Arc<Unparker>,
+    shutdown: AtomicBool,
+    // set once `mio::Poll` fails for good; I/O reports it from then on
+    failure: OnceLock<(io::ErrorKind, String)>,
+    #[cfg(target_os = "linux")]
+    uring: Option<Arc<Uring>>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<Reactor>>> = const { RefCell::new(None) };
+}
+
+impl Reactor {
+    /// Creates a reactor together with the `mio::Poll` that feeds it.
+    ///
+    /// Whoever owns the returned poll is the driver: either the `reactor`
+    /// thread or a current-thread executor.
+    ///
+    /// Asking for `IoBackend::IoUring` where io_uring can't be set up gets
+    /// a reactor without it, see `io_backend`.
+    pub(crate) fn new(backend: IoBackend) -> io::Result<(Arc<Self>, mio::Poll)> {
+        let poll = mio::Poll::new()?;
+        let registry = poll.registry().try_clone()?;
+        let waker = mio::Waker::new(&registry, WAKE_TOKEN)?;
+
+        #[cfg(target_os = "linux")]
+        let uring = match backend {
+            IoBackend::Epoll => None,
+            IoBackend::IoUring => {
+                let uring = Uring::new().and_then(|uring| {
+                    let fd = std::os::fd::AsRawFd::as_raw_fd(&uring);
+                    registry.register(
+                        &mut mio::unix::SourceFd(&fd),
+                        URING_TOKEN,
+                        Interest::READABLE,
+                    )?;
+                    Ok(Arc::new(uring))
+                });
+                #[cfg(feature = "tracing")]
+                if let Err(error) = &uring {
+                    event!(info, %error, "io_uring is not available, falling back to epoll");
+                }
+                uring.ok()
+            }
+        };
+        #[cfg(not(target_os = "linux"))]
+        let _ = backend;
+
+        let reactor = Reactor {
+            registry,
+            registrations: Mutex::new(Slab::default()),
+            timers: Mutex::new(Timers::default()),
+            unparker: Arc::new(Unparker {
+                parked: AtomicBool::new(false),
+                waker,
+            }),
+            shutdown: AtomicBool::new(false),
+            failure: OnceLock::new(),
+            #[cfg(target_os = "linux")]
+            uring,
+        };
+
+        Ok((Arc::new(reactor), poll))
+    }
+
+    /// Returns the reactor of the executor running on this thread.
+    ///
+    /// Outside of an executor this falls back to a process-wide reactor
+    /// driven by its own `reactor` thread.
+    ///
+    /// # Panics
+    ///
+    /// If that fallback reactor has to be created and can't be; see
+    /// `try_get`.
+    pub fn get() -> Arc<Self> {
+        Self::try_get().expect("failed to start the fallback reactor")
+    }
+
+    /// Like `get`, but reports a failure to create the fallback reactor.
+    pub fn try_get() -> io::Result<Arc<Self>> {
+        if let Some(reactor) = CURRENT.with(|current| current.borrow().clone()) {
+            return Ok(reactor);
+        }
+
+        static REACTOR: Mutex<Option<Arc<Reactor>>> = Mutex::new(None);
+
+        let mut global = lock(&REACTOR);
+        if let Some(reactor) = &*global {
+            return Ok(reactor.clone());
+        }
+        let (reactor, poll) = Reactor::new(IoBackend::Epoll)?;
+        reactor.spawn_thread(poll)?;
+        Ok(global.insert(reactor).clone())
+    }
+
+    /// Makes this reactor the one `Reactor::get` returns on this thread
+    /// until the guard is dropped.
+    pub(crate) fn enter(self: &Arc<Self>) -> EnterGuard {
+        let previous = CURRENT.with(|current| current.borrow_mut().replace(self.clone()));
+        EnterGuard { previous }
+    }
+
+    pub(crate) fn unparker(&self) -> Arc<Unparker> {
+        self.unparker.clone()
+    }
+
+    /// The backend actually in use, which is `Epoll` if io_uring was asked
+    /// for but isn't available.
+    pub fn io_backend(&self) -> IoBackend {
+        #[cfg(target_os = "linux")]
+        if self.uring.is_some() {
+            return IoBackend::IoUring;
+        }
+        IoBackend::Epoll
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(crate) fn uring(&self) -> Option<&Arc<Uring>> {
+        self.uring.as_ref()
+    }
+
+    pub(crate) fn spawn_thread(
+        self: &Arc<Self>,
+        poll: mio::Poll,
+    ) -> io::Result<std::thread::JoinHandle<()>> {
+        let reactor = self.clone();
+
+        std::thread::Builder::new()
+            .name("reactor".to_owned())
+            .spawn(move || run(reactor, poll))
+    }
+
+    /// Asks the `reactor` thread to return after its current turn.
+    pub(crate) fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        let _ = self.unparker.waker.wake();
+    }
+
+    /// Drops every stored waker, which breaks the task -> spawner cycles
+    /// of tasks that will never be polled again.
+    pub(crate) fn clear(&self) {
+        let wakers = lock(&self.registrations).take_wakers();
+        let timers = std::mem::take(&mut lock(&self.timers).entries);
+        drop((wakers, timers));
+
+        #[cfg(target_os = "linux")]
+        if let Some(uring) = &self.uring {
+            drop(uring.take_wakers());
+        }
+    }
+
+    /// The error every I/O operation fails with once `mio::Poll` has
+    /// failed, if it has.
+    pub(crate) fn failure(&self) -> Option<io::Error> {
+        let (kind, message) = self.failure.get()?;
+        Some(io::Error::new(*kind, format!("reactor failed: {message}")))
+    }
+
+    /// Gives up on `mio::Poll` after an unrecoverable error, and wakes
+    /// every task waiting for I/O so that it sees the error.
+    pub(crate) fn fail(&self, error: io::Error) {
+        event!(error, %error, "the reactor failed, I/O fails from now on");
+        let _ = self.failure.set((error.kind(), error.to_string()));
+        let wakers = lock(&self.registrations).take_wakers();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Waits for I/O events or the next timer, for at most `timeout`, and
+    /// wakes the tasks waiting on them.
+    ///
+    /// The driver must have called `Unparker::park` before, so that timers
+    /// inserted while it computes its timeout can still interrupt it.
+    pub(crate) fn turn(
+        &self,
+        poll: &mut mio::Poll,
+        events: &mut mio::Events,
+        timeout: Option<Duration>,
+    ) {
+        let timeout = match (timeout, self.next_timer_timeout()) {
+            (Some(timeout), Some(timer)) => Some(timeout.min(timer)),
+            (timeout, timer) => timeout.or(timer),
+        };
+
+        if self.failure.get().is_some() {
+            std::thread::sleep(timeout.map_or(FAILED_TICK, |timeout| timeout.min(FAILED_TICK)));
+            events.clear();
+        } else if let Err(error) = poll_retrying(poll, events, timeout) {
+            self.fail(error);
+        }
+
+        let mut wakers = Vec::new();
+        let mut guard = lock(&self.registrations);
+
+        for event in events.iter() {
+            if event.token() == WAKE_TOKEN {
+                continue;
+            }
+            #[cfg(target_os = "linux")]
+            if event.token() == URING_TOKEN {
+                continue;
+            }
+
+            event!(
+                trace,
+                token = event.token().0,
+                readable = event.is_readable(),
+                writable = event.is_writable(),
+                read_closed = event.is_read_closed(),
+                write_closed = event.is_write_closed(),
+                error = event.is_error(),
+                "I/O event"
+            );
+
+            // The slot may have been freed, or even handed to a new source,
+            // since this event was queued. Its generation no longer matches.
+            let Some(io) = guard.get_mut(event.token()) else {
+                event!(
+                    trace,
+                    token = event.token().0,
+                    "dropped an event for a stale token"
+                );
+                continue;
+            };
+
+            io.tick = io.tick.wrapping_add(1);
+
+            if event.is_readable() || event.is_read_closed() || event.is_error() {
+                io.readable = true;
+                wakers.extend(io.reader.take());
+            }
+            if event.is_writable() || event.is_write_closed() || event.is_error() {
+                io.writable = true;
+                wakers.extend(io.writer.take());
+            }
+        }
+
+        drop(guard);
+        wakers.extend(self.expired_timers());
+
+        // Reaping costs no syscall, so it isn't worth checking for the
+        // ring's event; and a failed reactor keeps completing operations.
+        #[cfg(target_os = "linux")]
+        if let Some(uring) = &self.uring {
+            wakers.extend(uring.reap());
+        }
+
+        // wake outside of the lock, the woken tasks may be polled right away
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// Polls, retrying for the rest of `timeout` when a signal interrupts it.
+fn poll_retrying(
+    poll: &mut mio::Poll,
+    events: &mut mio::Events,
+    timeout: Option<Duration>,
+) -> io::Result<()> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    loop {
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        match poll.poll(events, remaining) {
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {
+                event!(trace, "poll interrupted by a signal, retrying");
+                continue;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn run(reactor: Arc<Reactor>, mut poll: mio::Poll) {
+    let mut events = mio::Events::with_capacity(1024);
+
+    while !reactor.shutdown.load(Ordering::SeqCst) {
+        reactor.unparker.park();
+        reactor.turn(&mut poll, &mut events, None);
+        reactor.unparker.unpark();
+    }
+}
+
+pub(crate) struct EnterGuard {
+    previous: Option<Arc<Reactor>>,
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Interrupts whoever is blocked in `mio::Poll`: the `reactor` thread or
+/// a current-thread executor.
+pub(crate) struct Unparker {
+    parked: AtomicBool,
+    waker: mio::Waker,
+}
+
+impl Unparker {
+    pub(crate) fn park(&self) {
+        self.parked.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn unpark(&self) {
+        self.parked.store(false, Ordering::SeqCst);
+    }
+
+    /// Only pays for the `mio::Waker` syscall when the driver is actually
+    /// sleeping in `mio::Poll`.
+    pub(crate) fn wake(&self) {
+        if self.parked.load(Ordering::SeqCst) {
+            let _ = self.waker.wake();
+        }
+    }
+}
+
+impl Reactor {
+    /// Registers `source` under a fresh token from the registration table.
+    pub(crate) fn register(
+        &self,
+        source: &mut impl Source,
+        interests: Interest,
+    ) -> io::Result<Token> {
+        if let Some(error) = self.failure() {
+            return Err(error);
+        }
+        let token = lock(&self.registrations).insert()?;
+
+        // before its first event can be reported
+        event!(debug, token = token.0, ?interests, "source registered");
+        if let Err(error) = self.registry.register(source, token, interests) {
+            event!(debug, token = token.0, %error, "registration failed");
+            lock(&self.registrations).remove(token);
+            return Err(error);
+        }
+
+        Ok(token)
+    }
+
+    /// Deregisters `source` and frees its token for reuse.
+    ///
+    /// Any waker still stored for the token is dropped, and events already
+    /// queued for it are ignored once they arrive.
+    pub(crate) fn deregister(&self, source: &mut impl Source, token: Token) -> io::Result<()> {
+        event!(debug, token = token.0, "source deregistered");
+        let result = self.registry.deregister(source);
+        let io = lock(&self.registrations).remove(token);
+        drop(io);
+        result
+    }
+}
+
+impl Reactor {
+    /// Resolves with the current tick once `direction` is ready, storing
+    /// the task's waker otherwise.
+    pub(crate) fn poll_ready(
+        &self,
+        token: Token,
+        direction: Direction,
+        cx: &mut Context,
+    ) -> Poll<io::Result<usize>> {
+        if let Some(error) = self.failure() {
+            return Poll::Ready(Err(error));
+        }
+
+        let mut guard = lock(&self.registrations);
+        let Some(io) = guard.get_mut(token) else {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "token is not registered with this reactor",
+            )));
+        };
+
+        if *io.ready(direction) {
+            return Poll::Ready(Ok(io.tick));
+        }
+
+        // If there was already a waker there, we update it
+        // if it’s different from the waker in our current context
+        let slot = io.waker(direction);
+        match slot {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => *slot = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+
+    /// Forgets the readiness observed at `tick`, unless an event has been
+    /// delivered since, in which case the readiness is fresh and stays.
+    pub(crate) fn clear_ready(&self, token: Token, direction: Direction, tick: usize) {
+        let mut guard = lock(&self.registrations);
+        if let Some(io) = guard.get_mut(token) {
+            if io.tick == tick {
+                *io.ready(direction) = false;
+            }
+        }
+    }
+}
+
+/// A source's membership in a reactor, shared by the runtime's I/O types.
+pub(crate) struct Registration {
+    reactor: Arc<Reactor>,
+    token: Token,
+}
+
+impl Registration {
+    pub(crate) fn new(source: &mut impl Source, interests: Interest) -> io::Result<Self> {
+        let reactor = Reactor::try_get()?;
+        let token = reactor.register(source, interests)?;
+
+        Ok(Registration { reactor, token })
+    }
+
+    /// Every I/O operation comes through here, so this is where it is
+    /// charged to the task's cooperative budget.
+    pub(crate) fn poll_ready(
+        &self,
+        direction: Direction,
+        cx: &mut Context,
+    ) -> Poll<io::Result<usize>> {
+        std::task::ready!(super::coop::poll_proceed(cx));
+        self.reactor.poll_ready(self.token, direction, cx)
+    }
+
+    pub(crate) fn clear_ready(&self, direction: Direction, tick: usize) {
+        self.reactor.clear_ready(self.token, direction, tick)
+    }
+
+    /// Runs the non-blocking operation `f` until it stops returning
+    /// `WouldBlock`, sleeping on `direction` in between attempts.
+    pub(crate) async fn async_io<R>(
+        &self,
+        direction: Direction,
+        mut f: impl FnMut() -> io::Result<R>,
+    ) -> io::Result<R> {
+        loop {
+            let tick = std::future::poll_fn(|cx| self.poll_ready(direction, cx)).await?;
+
+            match f() {
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    self.clear_ready(direction, tick)
+                }
+                result => return self.report(direction, result),
+            }
+        }
+    }
+
+    /// Passes `result` through, emitting an event if the operation failed.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn report<R>(&self, direction: Direction, result: io::Result<R>) -> io::Result<R> {
+        #[cfg(feature = "tracing")]
+        if let Err(error) = &result {
+            event!(debug, token = self.token.0, ?direction, %error, "I/O error");
+        }
+        result
+    }
+
+    pub(crate) fn deregister(&self, source: &mut impl Source) -> io::Result<()> {
+        self.reactor.deregister(source, self.token)
+    }
+
+    /// The ring of the reactor the source is registered with, if it has one.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn uring(&self) -> Option<&Arc<Uring>> {
+        self.reactor.uring()
+    }
+}
+
+/// Identifies a timer: its deadline plus a tie-breaker for equal deadlines.
+pub(crate) type TimerKey = (Instant, u64);
+
+#[derive(Default)]
+struct Timers {
+    entries: BTreeMap<TimerKey, Waker>,
+    next_id: u64,
+}
+
+impl Reactor {
+    /// Arms a timer that wakes `waker` once `deadline` has passed.
+    pub(crate) fn insert_timer(&self, deadline: Instant, waker: Waker) -> TimerKey {
+        let mut timers = lock(&self.timers);
+
+        let key = (deadline, timers.next_id);
+        timers.next_id += 1;
+
+        let earliest = timers
+            .entries
+            .keys()
+            .next()
+            .is_none_or(|first| key < *first);
+        timers.entries.insert(key, waker);
+        drop(timers);
+
+        // a sleeping driver computed its timeout without this timer
+        if earliest {
+            self.unparker.wake();
+        }
+
+        key
+    }
+
+    /// Replaces the waker of an armed timer. Returns `false` if the timer
+    /// has already fired.
+    pub(crate) fn update_timer(&self, key: TimerKey, waker: &Waker) -> bool {
+        match lock(&self.timers).entries.get_mut(&key) {
+            Some(stored) => {
+                if !stored.will_wake(waker) {
+                    *stored = waker.clone();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn remove_timer(&self, key: TimerKey) {
+        let waker = lock(&self.timers).entries.remove(&key);
+        drop(waker);
+    }
+
+    /// How long the driver may sleep before the next timer is due, rounded
+    /// up to whole milliseconds, which is all `epoll` can wait for.
+    fn next_timer_timeout(&self) -> Option<Duration> {
+        let timers = lock(&self.timers);
+        let (deadline, _) = timers.entries.keys().next()?;
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let millis = remaining.as_nanos().div_ceil(1_000_000);
+        Some(Duration::from_millis(millis as u64))
+    }
+
+    fn expired_timers(&self) -> Vec<Waker> {
+        let mut timers = lock(&self.timers);
+        let now = Instant::now();
+
+        // everything at or before `now` is due, whatever its id
+        let pending = timers.entries.split_off(&(now, u64::MAX));
+        let expired = std::mem::replace(&mut timers.entries, pending);
+        expired.into_values().collect()
+    }
+}
+
+// Tokens carry the slot index in their low bits and the slot's generation in
+// the high bits, so an event for a freed slot can't reach its next owner.
+const INDEX_BITS: u32 = 24;
+const INDEX_MASK: usize = (1 << INDEX_BITS) - 1;
+// The two highest indexes are never handed out, which keeps `WAKE_TOKEN`
+// and `URING_TOKEN` unique.
+const MAX_SLOTS: usize = INDEX_MASK - 1;
+
+/// The registration table: one slot per registered source, reused once the
+/// source is deregistered.
+#[derive(Default)]
+struct Slab {
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+}
+
+struct Slot {
+    generation: usize,
+    registered: bool,
+    io: ScheduledIo,
+}
+
+impl Slab {
+    fn insert(&mut self) -> io::Result<Token> {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None if self.slots.len() < MAX_SLOTS => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    registered: false,
+                    io: ScheduledIo::default(),
+                });
+                self.slots.len() - 1
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::OutOfMemory,
+                    "reactor registration table is full",
+                ))
+            }
+        };
+
+        let slot = &mut self.slots[index];
+        slot.registered = true;
+        // Sources start out optimistically ready, the first attempt tells.
+        slot.io = ScheduledIo {
+            readable: true,
+            writable: true,
+            ..ScheduledIo::default()
+        };
+
+        Ok(Token(slot.generation << INDEX_BITS | index))
+    }
+
+    fn get_mut(&mut self, token: Token) -> Option<&mut ScheduledIo> {
+        let (index, generation) = (token.0 & INDEX_MASK, token.0 >> INDEX_BITS);
+
+        match self.slots.get_mut(index) {
+            Some(slot) if slot.registered && slot.generation == generation => Some(&mut slot.io),
+            _ => None,
+        }
+    }
+
+    /// Frees the slot behind `token`, returning whatever state it held.
+    fn remove(&mut self, token: Token) -> Option<ScheduledIo> {
+        let io = std::mem::take(self.get_mut(token)?);
+
+        let index = token.0 & INDEX_MASK;
+        let slot = &mut self.slots[index];
+        slot.registered = false;
+        slot.generation = (slot.generation + 1) & (usize::MAX >> INDEX_BITS);
+        self.free.push(index);
+
+        Some(io)
+    }
+
+    /// Takes every stored waker while keeping the registrations alive.
+    fn take_wakers(&mut self) -> Vec<Waker> {
+        self.slots
+            .iter_mut()
+            .flat_map(|slot| [slot.io.reader.take(), slot.io.writer.take()])
+            .flatten()
+            .collect()
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_tokens_are_reused_with_a_new_generation() {
+        let mut slab = Slab::default();
+
+        let first = slab.insert().unwrap();
+        slab.remove(first);
+        let second = slab.insert().unwrap();
+
+        assert_eq!(first.0 & INDEX_MASK, second.0 & INDEX_MASK);
+        assert_ne!(first, second);
+        assert_eq!(slab.slots.len(), 1);
+    }
+
+    #[test]
+    fn stale_tokens_do_not_reach_the_new_owner() {
+        let mut slab = Slab::default();
+
+        let stale = slab.insert().unwrap();
+        slab.remove(stale);
+        let fresh = slab.insert().unwrap();
+
+        assert!(slab.get_mut(stale).is_none());
+        assert!(slab.remove(stale).is_none());
+        assert!(slab.get_mut(fresh).is_some());
+    }
+
+    #[test]
+    fn removing_a_slot_clears_its_state() {
+        let mut slab = Slab::default();
+
+        let token = slab.insert().unwrap();
+        slab.get_mut(token).unwrap().tick = 7;
+
+        assert_eq!(slab.remove(token).unwrap().tick, 7);
+        let token = slab.insert().unwrap();
+        assert_eq!(slab.get_mut(token).unwrap().tick, 0);
+    }
+
+    fn noop_waker() -> Waker {
+        use std::task::{RawWaker, RawWakerVTable};
+
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(
+            |_| RawWaker::new(std::ptr::null(), &VTABLE),
+            |_| {},
+            |_| {},
+            |_| {},
+        );
+        unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
+    }
+
+    #[test]
+    fn readiness_delivered_after_the_snapshot_is_not_cleared() {
+        let (reactor, _poll) = Reactor::new(IoBackend::Epoll).unwrap();
+        let mut socket = mio::net::UdpSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let token = reactor.register(&mut socket, Interest::READABLE).unwrap();
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let Poll::Ready(Ok(tick)) = reactor.poll_ready(token, Direction::Read, &mut cx) else {
+            panic!("a fresh registration starts out ready");
+        };
+
+        // an event lands between the WouldBlock and the clear
+        reactor
+            .registrations
+            .lock()
+            .unwrap()
+            .get_mut(token)
+            .unwrap()
+            .tick += 1;
+        reactor.clear_ready(token, Direction::Read, tick);
+        assert!(reactor
+            .poll_ready(token, Direction::Read, &mut cx)
+            .is_ready());
+
+        // without a new event the consumed readiness is cleared
+        let Poll::Ready(Ok(tick)) = reactor.poll_ready(token, Direction::Read, &mut cx) else {
+            unreachable!()
+        };
+        reactor.clear_ready(token, Direction::Read, tick);
+        assert!(reactor
+            .poll_ready(token, Direction::Read, &mut cx)
+            .is_pending());
+        assert!(reactor
+            .poll_ready(token, Direction::Write, &mut cx)
+            .is_ready());
+    }
+
+    #[test]
+    fn churning_sockets_does_not_grow_the_table() {
+        let (reactor, _poll) = Reactor::new(IoBackend::Epoll).unwrap();
+
+        for _ in 0..100 {
+            let mut socket = mio::net::UdpSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            let token = reactor.register(&mut socket, Interest::READABLE).unwrap();
+            reactor.deregister(&mut socket, token).unwrap();
+        }
+
+        let registrations = reactor.registrations.lock().unwrap();
+        assert_eq!(registrations.len(), 0);
+        assert_eq!(registrations.slots.len(), 1);
+    }
+
+    #[test]
+    fn a_failed_reactor_fails_io_instead_of_panicking() {
+        use crate::executor::{sleep, sync::oneshot, Builder, UdpSocket};
+
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let (pending, fresh) = executor.block_on(async move {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let (sender, receiver) = oneshot::channel();
+            spawner.spawn(async move {
+                let _ = sender.send(socket.recv_from(&mut [0; 16]).await);
+            });
+            sleep(Duration::from_millis(5)).await;
+
+            Reactor::get().fail(io::Error::other("epoll went away"));
+
+            // timers keep running without `mio::Poll`
+            sleep(Duration::from_millis(20)).await;
+            (
+                receiver.await.unwrap(),
+                UdpSocket::bind("127.0.0.1:0").err(),
+            )
+        });
+
+        let error = pending.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+        assert!(error.to_string().contains("epoll went away"), "{error}");
+        assert!(fresh.is_some());
+    }
+
+    #[test]
+    fn interrupted_polls_are_retried() {
+        use crate::executor::{signal, sleep, Builder};
+
+        // handle SIGINT, so that it interrupts the poll instead of the test
+        let _signals = signal::shutdown().unwrap();
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        let polling = unsafe { libc::pthread_self() };
+        let interrupter = std::thread::spawn(move || {
+            for _ in 0..5 {
+                std::thread::sleep(Duration::from_millis(5));
+                unsafe { libc::pthread_kill(polling, libc::SIGINT) };
+            }
+        });
+
+        let start = Instant::now();
+        executor.block_on(sleep(Duration::from_millis(60)));
+        interrupter.join().unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(60));
+    }
+}
@@ -0,0 +1,435 @@
+//! A small cron-like scheduler for periodic jobs.
+//!
+//! Schedules are written as
+//!
+//! - `@every 30s`: every 30 seconds, counted from when the job is added;
+//! - `*/5m`: at every wall-clock multiple of five minutes, like cron's
+//!   `*/5` minute field;
+//! - `*/1h+15m`: the same, shifted by an offset, here at quarter past
+//!   every hour.
+//!
+//! Durations take `ms`, `s`, `m` or `h` suffixes. A job never overlaps
+//! itself: if a run overruns its period, the job's `MissedTickBehavior`
+//! decides what happens to the runs it missed.
+
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::Poll,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    sync::oneshot,
+    time::{duration_from_nanos, interval_at, MissedTickBehavior},
+    Spawner,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// Every `period`, the first run one period after the job is added.
+    Every(Duration),
+    /// At every wall-clock multiple of `period` since the Unix epoch,
+    /// plus `offset`.
+    Aligned { period: Duration, offset: Duration },
+}
+
+impl Schedule {
+    /// The first run on or after `now`, or `None` if it is later than an
+    /// `Instant` can hold.
+    fn first_run(&self, now: Instant, wall: SystemTime) -> Option<Instant> {
+        match *self {
+            Schedule::Every(period) => now.checked_add(period),
+            Schedule::Aligned { period, offset } => {
+                let since_epoch = wall.duration_since(UNIX_EPOCH).unwrap_or_default();
+                let into_period = (since_epoch.as_nanos() + period.as_nanos()
+                    - offset.as_nanos() % period.as_nanos())
+                    % period.as_nanos();
+                let wait = match into_period {
+                    0 => 0,
+                    into_period => period.as_nanos() - into_period,
+                };
+                // less than `period`, so it converts
+                now.checked_add(duration_from_nanos(wait)?)
+            }
+        }
+    }
+
+    fn period(&self) -> Duration {
+        match *self {
+            Schedule::Every(period) | Schedule::Aligned { period, .. } => period,
+        }
+    }
+}
+
+/// A schedule string that doesn't follow the syntax above.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseScheduleError(String);
+
+impl fmt::Display for ParseScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid schedule: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseScheduleError {}
+
+fn parse_duration(s: &str) -> Result<Duration, ParseScheduleError> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| ParseScheduleError(format!("`{s}` has no unit")))?;
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| ParseScheduleError(format!("`{s}` has no number")))?;
+
+    let secs = |per_unit: u64| {
+        value
+            .checked_mul(per_unit)
+            .map(Duration::from_secs)
+            .ok_or_else(|| ParseScheduleError(format!("`{s}` is too long")))
+    };
+    let duration = match unit {
+        "ms" => Duration::from_millis(value),
+        "s" => Duration::from_secs(value),
+        "m" => secs(60)?,
+        "h" => secs(60 * 60)?,
+        _ => return Err(ParseScheduleError(format!("unknown unit `{unit}`"))),
+    };
+    match duration.is_zero() {
+        true => Err(ParseScheduleError(format!("`{s}` is zero"))),
+        false => Ok(duration),
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ParseScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, ParseScheduleError> {
+        let s = s.trim();
+
+        let schedule = if let Some(period) = s.strip_prefix("@every ") {
+            Schedule::Every(parse_duration(period.trim())?)
+        } else if let Some(aligned) = s.strip_prefix("*/") {
+            let (period, offset) = match aligned.split_once('+') {
+                Some((period, offset)) => (parse_duration(period)?, parse_duration(offset)?),
+                None => (parse_duration(aligned)?, Duration::ZERO),
+            };
+            Schedule::Aligned { period, offset }
+        } else {
+            return Err(ParseScheduleError(format!(
+                "`{s}`, expected `@every <duration>` or `*/<duration>[+<offset>]`"
+            )));
+        };
+
+        // a run is never more than a period away
+        match Instant::now().checked_add(schedule.period()) {
+            Some(_) => Ok(schedule),
+            None => Err(ParseScheduleError(format!("`{s}` is too long"))),
+        }
+    }
+}
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+type Job = Arc<dyn Fn() -> BoxFuture + Send + Sync>;
+
+/// Runs jobs on their schedules, each on its own task, until the job's
+/// handle or the scheduler is dropped.
+pub struct Scheduler {
+    spawner: Spawner,
+    jobs: Vec<JobHandle>,
+}
+
+/// Cancels its job when dropped, or when `cancel` is called.
+pub struct JobHandle {
+    name: String,
+    runs: Arc<AtomicU64>,
+    _cancel: oneshot::Sender<()>,
+}
+
+impl JobHandle {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// How many times the job has completed.
+    pub fn runs(&self) -> u64 {
+        self.runs.load(Ordering::Relaxed)
+    }
+
+    /// Stops the job; a run in progress is dropped at its next await.
+    pub fn cancel(self) {}
+}
+
+impl Scheduler {
+    pub fn new(spawner: Spawner) -> Self {
+        Scheduler {
+            spawner,
+            jobs: Vec::new(),
+        }
+    }
+
+    /// Adds a job that lives as long as the scheduler.
+    pub fn add<F, Fut>(&mut self, name: &str, schedule: Schedule, job: F) -> &mut Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handle = self.spawn(name, schedule, MissedTickBehavior::Skip, job);
+        self.jobs.push(handle);
+        self
+    }
+
+    /// Starts a job and hands back its handle, which controls its lifetime.
+    ///
+    /// `behavior` decides what happens to runs missed while a previous run
+    /// overran; periodic jobs usually want `Skip`.
+    pub fn spawn<F, Fut>(
+        &self,
+        name: &str,
+        schedule: Schedule,
+        behavior: MissedTickBehavior,
+        job: F,
+    ) -> JobHandle
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let job: Job = Arc::new(move || Box::pin(job()));
+        let runs = Arc::new(AtomicU64::new(0));
+        let (cancel, canceled) = oneshot::channel();
+
+        // `None` for a schedule built by hand too far out to ever run
+        let start = schedule.first_run(Instant::now(), SystemTime::now());
+        self.spawner.spawn(run_job(
+            job,
+            start,
+            schedule.period(),
+            behavior,
+            runs.clone(),
+            canceled,
+        ));
+
+        JobHandle {
+            name: name.to_owned(),
+            runs,
+            _cancel: cancel,
+        }
+    }
+
+    pub fn jobs(&self) -> &[JobHandle] {
+        &self.jobs
+    }
+}
+
+async fn run_job(
+    job: Job,
+    start: Option<Instant>,
+    period: Duration,
+    behavior: MissedTickBehavior,
+    runs: Arc<AtomicU64>,
+    mut canceled: oneshot::Receiver<()>,
+) {
+    let mut interval = start.map(|start| interval_at(start, period));
+    if let Some(interval) = &mut interval {
+        interval.set_missed_tick_behavior(behavior);
+    }
+    let mut running: Option<BoxFuture> = None;
+
+    std::future::poll_fn(|cx| loop {
+        if Pin::new(&mut canceled).poll(cx).is_ready() {
+            return Poll::Ready(());
+        }
+
+        match &mut running {
+            Some(run) => match run.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    runs.fetch_add(1, Ordering::Relaxed);
+                    running = None;
+                }
+                Poll::Pending => return Poll::Pending,
+            },
+            None => match interval.as_mut().map_or(Poll::Pending, |i| i.poll_tick(cx)) {
+                Poll::Ready(_) => running = Some(job()),
+                Poll::Pending => return Poll::Pending,
+            },
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{sleep, Builder};
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn schedules_parse() {
+        assert_eq!(
+            "@every 30s".parse(),
+            Ok(Schedule::Every(Duration::from_secs(30)))
+        );
+        assert_eq!("@every 250ms".parse(), Ok(Schedule::Every(ms(250))));
+        assert_eq!(
+            "*/5m".parse(),
+            Ok(Schedule::Aligned {
+                period: Duration::from_secs(300),
+                offset: Duration::ZERO,
+            })
+        );
+        assert_eq!(
+            "*/1h+15m".parse(),
+            Ok(Schedule::Aligned {
+                period: Duration::from_secs(3600),
+                offset: Duration::from_secs(900),
+            })
+        );
+
+        for invalid in [
+            "every 5s",
+            "@every 5",
+            "*/0s",
+            "*/5d",
+            "@every s",
+            "@every 99999999999999999h",
+            "*/1h+999999999999999999m",
+            "@every 18446744073709551615s",
+            "*/18446744073709551615s",
+        ] {
+            assert!(invalid.parse::<Schedule>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn aligned_schedules_start_on_the_next_multiple() {
+        let now = Instant::now();
+        let wall = UNIX_EPOCH + Duration::from_secs(3600 * 1000 + 125);
+        let at = |s: &str| s.parse::<Schedule>().unwrap().first_run(now, wall).unwrap() - now;
+
+        // 125s past the hour
+        assert_eq!(at("*/1m"), Duration::from_secs(55));
+        assert_eq!(at("*/1h"), Duration::from_secs(3475));
+        assert_eq!(at("*/1h+5m"), Duration::from_secs(175));
+        assert_eq!(at("*/1h+2m"), Duration::from_secs(3595));
+        assert_eq!(at("*/5s"), Duration::ZERO);
+    }
+
+    #[test]
+    fn jobs_run_on_their_period_until_dropped() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let (heartbeats, flushes, elapsed) = executor.block_on(async move {
+            let start = Instant::now();
+            let mut scheduler = Scheduler::new(spawner);
+            scheduler
+                .add("heartbeat", Schedule::Every(ms(10)), || async {})
+                .add("stats-flush", Schedule::Every(ms(25)), || async {});
+
+            sleep(ms(110)).await;
+            let runs = (scheduler.jobs()[0].runs(), scheduler.jobs()[1].runs());
+            assert_eq!(scheduler.jobs()[1].name(), "stats-flush");
+            (runs.0, runs.1, start.elapsed())
+        });
+
+        // at most one run per period that actually passed
+        let periods = |period: Duration| (elapsed.as_nanos() / period.as_nanos()) as u64;
+        assert!((1..=periods(ms(10))).contains(&heartbeats), "{heartbeats}");
+        assert!((1..=periods(ms(25))).contains(&flushes), "{flushes}");
+    }
+
+    #[test]
+    fn overrunning_jobs_do_not_overlap() {
+        let (executor, spawner) = Builder::new().reactor_thread().build().unwrap();
+        let active = Arc::new(AtomicU64::new(0));
+        let overlapped = Arc::new(AtomicU64::new(0));
+
+        let (runs, elapsed) = executor.block_on({
+            let (active, overlapped) = (active.clone(), overlapped.clone());
+            async move {
+                let start = Instant::now();
+                let scheduler = Scheduler::new(spawner);
+                let job = scheduler.spawn(
+                    "slow",
+                    Schedule::Every(ms(5)),
+                    MissedTickBehavior::Skip,
+                    move || {
+                        let (active, overlapped) = (active.clone(), overlapped.clone());
+                        async move {
+                            if active.fetch_add(1, Ordering::SeqCst) > 0 {
+                                overlapped.fetch_add(1, Ordering::SeqCst);
+                            }
+                            sleep(ms(12)).await;
+                            active.fetch_sub(1, Ordering::SeqCst);
+                        }
+                    },
+                );
+
+                sleep(ms(100)).await;
+                let runs = job.runs();
+                job.cancel();
+                (runs, start.elapsed())
+            }
+        });
+
+        assert_eq!(overlapped.load(Ordering::SeqCst), 0);
+        // runs one after another take 12ms each
+        assert!(runs >= 1, "{runs}");
+        assert!(
+            runs as u128 * 12 <= elapsed.as_millis(),
+            "{runs} in {elapsed:?}"
+        );
+    }
+
+    #[test]
+    fn canceled_jobs_stop_running() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+        let count = Arc::new(AtomicU64::new(0));
+
+        let counted = count.clone();
+        let (before, after) = executor.block_on(async move {
+            let scheduler = Scheduler::new(spawner);
+            let job = scheduler.spawn(
+                "count",
+                Schedule::Every(ms(5)),
+                MissedTickBehavior::Skip,
+                move || {
+                    counted.fetch_add(1, Ordering::SeqCst);
+                    async {}
+                },
+            );
+
+            sleep(ms(22)).await;
+            job.cancel();
+            let before = count.load(Ordering::SeqCst);
+            sleep(ms(30)).await;
+            (before, count.load(Ordering::SeqCst))
+        });
+
+        assert!(before >= 1, "{before}");
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn a_schedule_past_the_last_instant_never_runs() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let runs = executor.block_on(async move {
+            let mut scheduler = Scheduler::new(spawner);
+            scheduler.add("never", Schedule::Every(Duration::MAX), || async {});
+            sleep(ms(10)).await;
+            scheduler.jobs()[0].runs()
+        });
+
+        assert_eq!(runs, 0);
+    }
+}
@@ -0,0 +1,384 @@
+//! Serial ports, such as a gateway's console.
+//!
+//! The port is put in raw mode, so bytes pass through unchanged: no echo,
+//! no line buffering, no translation of `\r` or control characters. Baud
+//! rate and framing are set through termios.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, ErrorKind, Read, Write},
+    mem::MaybeUninit,
+    os::{
+        fd::{AsRawFd, OwnedFd},
+        unix::fs::OpenOptionsExt,
+    },
+    path::Path,
+};
+
+use super::{async_fd::AsyncFd, reactor::Direction};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataBits {
+    Five,
+    Six,
+    Seven,
+    Eight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+/// Line settings, 115200 8N1 by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialConfig {
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        SerialConfig {
+            baud_rate: 115_200,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+        }
+    }
+}
+
+const BAUD_RATES: &[(u32, libc::speed_t)] = &[
+    (1200, libc::B1200),
+    (2400, libc::B2400),
+    (4800, libc::B4800),
+    (9600, libc::B9600),
+    (19_200, libc::B19200),
+    (38_400, libc::B38400),
+    (57_600, libc::B57600),
+    (115_200, libc::B115200),
+    (230_400, libc::B230400),
+    #[cfg(target_os = "linux")]
+    (460_800, libc::B460800),
+    #[cfg(target_os = "linux")]
+    (921_600, libc::B921600),
+];
+
+fn cvt(ret: libc::c_int) -> io::Result<()> {
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+fn set_config(termios: &mut libc::termios, config: &SerialConfig) -> io::Result<()> {
+    let speed = BAUD_RATES
+        .iter()
+        .find(|&&(rate, _)| rate == config.baud_rate)
+        .map(|&(_, speed)| speed)
+        .ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("unsupported baud rate {}", config.baud_rate),
+            )
+        })?;
+
+    unsafe {
+        libc::cfmakeraw(termios);
+        cvt(libc::cfsetispeed(termios, speed))?;
+        cvt(libc::cfsetospeed(termios, speed))?;
+    }
+
+    termios.c_cflag &= !(libc::CSIZE | libc::PARENB | libc::PARODD | libc::CSTOPB);
+    termios.c_cflag |= libc::CLOCAL | libc::CREAD;
+    termios.c_cflag |= match config.data_bits {
+        DataBits::Five => libc::CS5,
+        DataBits::Six => libc::CS6,
+        DataBits::Seven => libc::CS7,
+        DataBits::Eight => libc::CS8,
+    };
+    termios.c_cflag |= match config.parity {
+        Parity::None => 0,
+        Parity::Odd => libc::PARENB | libc::PARODD,
+        Parity::Even => libc::PARENB,
+    };
+    if config.stop_bits == StopBits::Two {
+        termios.c_cflag |= libc::CSTOPB;
+    }
+    // reads never wait in the kernel, the reactor does the waiting
+    termios.c_cc[libc::VMIN] = 0;
+    termios.c_cc[libc::VTIME] = 0;
+
+    Ok(())
+}
+
+fn get_config(termios: &libc::termios) -> io::Result<SerialConfig> {
+    let speed = unsafe { libc::cfgetospeed(termios) };
+    let baud_rate = BAUD_RATES
+        .iter()
+        .find(|&&(_, s)| s == speed)
+        .map(|&(rate, _)| rate)
+        .ok_or_else(|| io::Error::other(format!("unknown speed constant {speed:#o}")))?;
+
+    let data_bits = match termios.c_cflag & libc::CSIZE {
+        libc::CS5 => DataBits::Five,
+        libc::CS6 => DataBits::Six,
+        libc::CS7 => DataBits::Seven,
+        _ => DataBits::Eight,
+    };
+    let parity = match (
+        termios.c_cflag & libc::PARENB != 0,
+        termios.c_cflag & libc::PARODD != 0,
+    ) {
+        (false, _) => Parity::None,
+        (true, true) => Parity::Odd,
+        (true, false) => Parity::Even,
+    };
+    let stop_bits = match termios.c_cflag & libc::CSTOPB {
+        0 => StopBits::One,
+        _ => StopBits::Two,
+    };
+
+    Ok(SerialConfig {
+        baud_rate,
+        data_bits,
+        parity,
+        stop_bits,
+    })
+}
+
+/// A tty opened for non-blocking I/O through the reactor.
+pub struct SerialPort {
+    fd: AsyncFd<File>,
+}
+
+impl SerialPort {
+    /// Opens the tty at `path`, e.g. `/dev/ttyUSB0`, and configures it.
+    ///
+    /// The port never becomes the process's controlling terminal.
+    pub fn open(path: impl AsRef<Path>, config: &SerialConfig) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+            .open(path)?;
+
+        Self::configured(file, config)
+    }
+
+    /// Takes over an already open tty, such as one side of a pseudo
+    /// terminal, and configures it.
+    pub fn from_fd(fd: OwnedFd, config: &SerialConfig) -> io::Result<Self> {
+        let file = File::from(fd);
+        let fd = file.as_raw_fd();
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            cvt(flags)?;
+            cvt(libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK))?;
+        }
+
+        Self::configured(file, config)
+    }
+
+    fn configured(file: File, config: &SerialConfig) -> io::Result<Self> {
+        let port = SerialPort {
+            fd: AsyncFd::new(file)?,
+        };
+        port.configure(config)?;
+        Ok(port)
+    }
+
+    fn termios(&self) -> io::Result<libc::termios> {
+        let mut termios = MaybeUninit::uninit();
+        unsafe {
+            cvt(libc::tcgetattr(self.fd.get_ref().as_raw_fd(), termios.as_mut_ptr()))?;
+            Ok(termios.assume_init())
+        }
+    }
+
+    /// Applies `config` right away, in raw mode.
+    ///
+    /// Fails with `InvalidInput` for a baud rate termios has no constant for.
+    pub fn configure(&self, config: &SerialConfig) -> io::Result<()> {
+        let mut termios = self.termios()?;
+        set_config(&mut termios, config)?;
+
+        cvt(unsafe { libc::tcsetattr(self.fd.get_ref().as_raw_fd(), libc::TCSANOW, &termios) })
+    }
+
+    /// The settings currently in effect, as the driver reports them.
+    ///
+    /// A driver may ignore some of them: a pseudo terminal always reports
+    /// eight data bits and no parity.
+    pub fn config(&self) -> io::Result<SerialConfig> {
+        get_config(&self.termios()?)
+    }
+
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fd
+            .async_io(Direction::Read, |mut file| file.read(buf))
+            .await
+    }
+
+    pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        self.fd
+            .async_io(Direction::Write, |mut file| file.write(buf))
+            .await
+    }
+
+    pub async fn write_all(&self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits until everything written has been transmitted.
+    ///
+    /// This blocks the executor thread for as long as that takes, which at
+    /// low baud rates can be noticeable.
+    pub fn drain(&self) -> io::Result<()> {
+        cvt(unsafe { libc::tcdrain(self.fd.get_ref().as_raw_fd()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::fd::FromRawFd, ptr, time::Duration};
+
+    use super::*;
+    use crate::executor::{timeout, Builder, DriverMode};
+
+    /// A pseudo terminal: writes to one side are read from the other.
+    fn pty_pair() -> (OwnedFd, OwnedFd) {
+        let (mut controller, mut device) = (0, 0);
+        let ret = unsafe {
+            libc::openpty(
+                &mut controller,
+                &mut device,
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null(),
+            )
+        };
+        assert_eq!(ret, 0, "{}", io::Error::last_os_error());
+        unsafe { (OwnedFd::from_raw_fd(controller), OwnedFd::from_raw_fd(device)) }
+    }
+
+    async fn read_exact(port: &SerialPort, len: usize) -> Vec<u8> {
+        let mut received = Vec::new();
+        let mut buf = [0; 64];
+        while received.len() < len {
+            let n = timeout(Duration::from_secs(5), port.read(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            received.extend_from_slice(&buf[..n]);
+        }
+        received
+    }
+
+    #[test]
+    fn bytes_cross_a_pty_unchanged() {
+        for mode in [DriverMode::ReactorThread, DriverMode::CurrentThread] {
+            let (executor, _spawner) = Builder::new().mode(mode).build().unwrap();
+
+            executor.block_on(async {
+                let (controller, device) = pty_pair();
+                let config = SerialConfig::default();
+                let console = SerialPort::from_fd(device, &config).unwrap();
+                let gateway = SerialPort::from_fd(controller, &config).unwrap();
+
+                // raw mode: no echo, and no special meaning for these bytes
+                let command = b"status\r\n\x03\x00\x7f\xff";
+                console.write_all(command).await.unwrap();
+                assert_eq!(read_exact(&gateway, command.len()).await, command);
+
+                gateway.write_all(b"ok\n").await.unwrap();
+                assert_eq!(read_exact(&console, 3).await, b"ok\n");
+            });
+        }
+    }
+
+    #[test]
+    fn framing_round_trips_through_termios() {
+        for (data_bits, parity, stop_bits) in [
+            (DataBits::Eight, Parity::None, StopBits::One),
+            (DataBits::Seven, Parity::Even, StopBits::Two),
+            (DataBits::Five, Parity::Odd, StopBits::One),
+            (DataBits::Six, Parity::None, StopBits::Two),
+        ] {
+            let config = SerialConfig {
+                baud_rate: 57_600,
+                data_bits,
+                parity,
+                stop_bits,
+            };
+            let mut termios = unsafe { std::mem::zeroed() };
+
+            set_config(&mut termios, &config).unwrap();
+            assert_eq!(get_config(&termios).unwrap(), config);
+        }
+    }
+
+    #[test]
+    fn the_driver_applies_the_baud_rate() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        executor.block_on(async {
+            let (_controller, device) = pty_pair();
+            let port = SerialPort::from_fd(device, &SerialConfig::default()).unwrap();
+            assert_eq!(port.config().unwrap(), SerialConfig::default());
+
+            // a pty keeps the speed and stop bits, but not parity or size
+            let config = SerialConfig {
+                baud_rate: 9600,
+                stop_bits: StopBits::Two,
+                ..SerialConfig::default()
+            };
+            port.configure(&config).unwrap();
+            assert_eq!(port.config().unwrap(), config);
+        });
+    }
+
+    #[test]
+    fn unsupported_baud_rates_are_rejected() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        executor.block_on(async {
+            let (_controller, device) = pty_pair();
+            let config = SerialConfig {
+                baud_rate: 12_345,
+                ..SerialConfig::default()
+            };
+
+            let error = SerialPort::from_fd(device, &config).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        });
+    }
+
+    #[test]
+    fn opening_a_missing_device_fails() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        executor.block_on(async {
+            let error = SerialPort::open("/dev/does-not-exist", &SerialConfig::default())
+                .err()
+                .unwrap();
+            assert_eq!(error.kind(), ErrorKind::NotFound);
+        });
+    }
+}
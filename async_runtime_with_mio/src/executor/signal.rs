@@ -0,0 +1,175 @@
+//! Shutdown signals.
+//!
+//! A signal handler may do next to nothing, so it writes a byte to a pipe,
+//! and the read end is polled by the reactor like any other descriptor.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    os::fd::{AsRawFd, IntoRawFd, OwnedFd},
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        OnceLock,
+    },
+};
+
+use super::{async_fd::AsyncFd, reactor::Direction};
+
+// the write end of the pipe, never closed once the handlers are installed
+static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+// where the calling thread's `errno` lives, named differently by each libc
+#[cfg(any(target_os = "linux", target_os = "redox"))]
+unsafe fn errno() -> *mut libc::c_int {
+    unsafe { libc::__errno_location() }
+}
+
+#[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+unsafe fn errno() -> *mut libc::c_int {
+    unsafe { libc::__errno() }
+}
+
+#[cfg(any(
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+unsafe fn errno() -> *mut libc::c_int {
+    unsafe { libc::__error() }
+}
+
+extern "C" fn on_signal(_: libc::c_int) {
+    let fd = WRITE_FD.load(Ordering::Relaxed);
+    unsafe {
+        // the interrupted code may be about to read `errno`
+        let saved = *errno();
+        // a full pipe already holds a pending notification
+        libc::write(fd, [1u8].as_ptr().cast(), 1);
+        *errno() = saved;
+    }
+}
+
+fn set_nonblocking(fd: &impl AsRawFd) -> io::Result<()> {
+    let fd = fd.as_raw_fd();
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags == -1 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Creates the pipe and installs the handlers, once per process.
+///
+/// Every caller gets the outcome of that one attempt: concurrent callers
+/// wait for it, and later ones see its error rather than a pipe nobody
+/// writes to.
+fn install() -> io::Result<&'static OwnedFd> {
+    static READ_FD: OnceLock<io::Result<OwnedFd>> = OnceLock::new();
+
+    match READ_FD.get_or_init(try_install) {
+        Ok(fd) => Ok(fd),
+        Err(error) => Err(match error.raw_os_error() {
+            Some(code) => io::Error::from_raw_os_error(code),
+            None => io::Error::new(error.kind(), error.to_string()),
+        }),
+    }
+}
+
+fn try_install() -> io::Result<OwnedFd> {
+    let (reader, writer) = io::pipe()?;
+    set_nonblocking(&reader)?;
+    set_nonblocking(&writer)?;
+    WRITE_FD.store(writer.into_raw_fd(), Ordering::Relaxed);
+
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+
+    Ok(OwnedFd::from(reader))
+}
+
+/// Notified when the process gets SIGINT or SIGTERM.
+///
+/// Creating one replaces the default handlers, which would have killed the
+/// process, for good. Every signal is delivered to one listener only.
+pub struct Shutdown {
+    fd: AsyncFd<File>,
+}
+
+/// Starts listening for SIGINT and SIGTERM.
+pub fn shutdown() -> io::Result<Shutdown> {
+    let fd = install()?.try_clone()?;
+
+    Ok(Shutdown {
+        fd: AsyncFd::new(File::from(fd))?,
+    })
+}
+
+impl Shutdown {
+    /// Waits for the next signal.
+    pub async fn recv(&self) -> io::Result<()> {
+        let mut buf = [0; 1];
+        self.fd
+            .async_io(Direction::Read, |mut file| file.read(&mut buf))
+            .await
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::executor::{timeout, Builder};
+
+    #[test]
+    fn a_signal_wakes_the_listener() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        let received = executor.block_on(async {
+            let signals = shutdown().unwrap();
+
+            // the handler writes on whatever thread the signal lands on
+            unsafe { libc::raise(libc::SIGINT) };
+            timeout(Duration::from_secs(5), signals.recv()).await
+        });
+
+        assert!(matches!(received, Ok(Ok(()))));
+    }
+
+    #[test]
+    fn concurrent_installs_share_one_pipe() {
+        let fds: Vec<_> = (0..8)
+            .map(|_| std::thread::spawn(|| install().unwrap().as_raw_fd()))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+
+        assert!(fds.iter().all(|&fd| fd == fds[0]), "{fds:?}");
+    }
+
+    #[test]
+    fn the_handler_leaves_errno_alone() {
+        install().unwrap();
+
+        let saved = unsafe {
+            *errno() = libc::EINTR;
+            // runs the handler on this thread before returning
+            libc::raise(libc::SIGTERM);
+            *errno()
+        };
+        assert_eq!(saved, libc::EINTR);
+    }
+}
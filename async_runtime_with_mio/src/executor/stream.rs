@@ -0,0 +1,459 @@
+//! Asynchronous sequences of values and the adapters to combine them.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use super::time::{sleep_until, Sleep};
+
+/// An asynchronous iterator.
+///
+/// Every adapter requires `Unpin` streams and futures, which keeps them
+/// free of pin projection; box anything that isn't.
+pub trait Stream {
+    type Item;
+
+    /// Returns the next item if one is ready, `None` once the stream is
+    /// exhausted.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>>;
+
+    /// Waits for the next item.
+    fn next(&mut self) -> Next<'_, Self>
+    where
+        Self: Unpin + Sized,
+    {
+        Next { stream: self }
+    }
+
+    fn map<U, F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> U,
+    {
+        Map { stream: self, f }
+    }
+
+    /// Skips the items `predicate` returns `false` for.
+    fn filter<F>(self, predicate: F) -> Filter<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> bool,
+    {
+        Filter {
+            stream: self,
+            predicate,
+        }
+    }
+
+    /// Ends the stream after `n` items.
+    fn take(self, n: usize) -> Take<Self>
+    where
+        Self: Sized,
+    {
+        Take {
+            stream: self,
+            remaining: n,
+        }
+    }
+
+    /// Runs up to `limit` of the futures this stream yields at once,
+    /// yielding their outputs in completion order.
+    fn buffer_unordered(self, limit: usize) -> BufferUnordered<Self>
+    where
+        Self: Sized,
+        Self::Item: Future + Unpin,
+    {
+        BufferUnordered {
+            stream: Some(self),
+            running: Vec::new(),
+            limit: limit.max(1),
+        }
+    }
+
+    /// Groups items into batches of at most `max` items, yielding a batch
+    /// early once `timeout` has passed since its first item arrived.
+    fn chunks_timeout(self, max: usize, timeout: Duration) -> ChunksTimeout<Self>
+    where
+        Self: Sized,
+    {
+        ChunksTimeout {
+            stream: Some(self),
+            chunk: Vec::new(),
+            max: max.max(1),
+            timeout,
+            deadline: None,
+        }
+    }
+
+    /// Yields at most one item per `period`; items wait in the underlying
+    /// stream meanwhile.
+    fn throttle(self, period: Duration) -> Throttle<Self>
+    where
+        Self: Sized,
+    {
+        Throttle {
+            stream: self,
+            period,
+            delay: None,
+        }
+    }
+}
+
+impl<S: Stream + Unpin + ?Sized> Stream for &mut S {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        Pin::new(&mut **self).poll_next(cx)
+    }
+}
+
+impl<S: Stream + ?Sized> Stream for Pin<Box<S>> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        self.get_mut().as_mut().poll_next(cx)
+    }
+}
+
+/// Turns an iterator into a stream whose items are always ready.
+pub fn iter<I: IntoIterator>(iter: I) -> Iter<I::IntoIter> {
+    Iter {
+        iter: iter.into_iter(),
+    }
+}
+
+pub struct Iter<I> {
+    iter: I,
+}
+
+impl<I: Iterator + Unpin> Stream for Iter<I> {
+    type Item = I::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<I::Item>> {
+        Poll::Ready(self.iter.next())
+    }
+}
+
+pub struct Next<'a, S: ?Sized> {
+    stream: &'a mut S,
+}
+
+impl<S: Stream + Unpin + ?Sized> Future for Next<'_, S> {
+    type Output = Option<S::Item>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut *self.stream).poll_next(cx)
+    }
+}
+
+pub struct Map<S, F> {
+    stream: S,
+    f: F,
+}
+
+impl<S, F, U> Stream for Map<S, F>
+where
+    S: Stream + Unpin,
+    F: FnMut(S::Item) -> U + Unpin,
+{
+    type Item = U;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<U>> {
+        let this = &mut *self;
+        Pin::new(&mut this.stream)
+            .poll_next(cx)
+            .map(|item| item.map(&mut this.f))
+    }
+}
+
+pub struct Filter<S, F> {
+    stream: S,
+    predicate: F,
+}
+
+impl<S, F> Stream for Filter<S, F>
+where
+    S: Stream + Unpin,
+    F: FnMut(&S::Item) -> bool + Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let this = &mut *self;
+        loop {
+            match Pin::new(&mut this.stream).poll_next(cx) {
+                Poll::Ready(Some(item)) if !(this.predicate)(&item) => continue,
+                poll => return poll,
+            }
+        }
+    }
+}
+
+pub struct Take<S> {
+    stream: S,
+    remaining: usize,
+}
+
+impl<S: Stream + Unpin> Stream for Take<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        if self.remaining == 0 {
+            return Poll::Ready(None);
+        }
+
+        let poll = Pin::new(&mut self.stream).poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(_)) => self.remaining -= 1,
+            Poll::Ready(None) => self.remaining = 0,
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
+
+pub struct BufferUnordered<S: Stream> {
+    /// `None` once the underlying stream is exhausted.
+    stream: Option<S>,
+    running: Vec<S::Item>,
+    limit: usize,
+}
+
+impl<S> Stream for BufferUnordered<S>
+where
+    S: Stream + Unpin,
+    S::Item: Future + Unpin,
+{
+    type Item = <S::Item as Future>::Output;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        // top up to the limit first so new futures get polled right away
+        while this.running.len() < this.limit {
+            let Some(stream) = &mut this.stream else {
+                break;
+            };
+            match Pin::new(stream).poll_next(cx) {
+                Poll::Ready(Some(future)) => this.running.push(future),
+                Poll::Ready(None) => this.stream = None,
+                Poll::Pending => break,
+            }
+        }
+
+        // every future shares our waker, so poll them all on each wake-up
+        for i in 0..this.running.len() {
+            if let Poll::Ready(output) = Pin::new(&mut this.running[i]).poll(cx) {
+                drop(this.running.swap_remove(i));
+                return Poll::Ready(Some(output));
+            }
+        }
+
+        if this.stream.is_none() && this.running.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+pub struct ChunksTimeout<S: Stream> {
+    stream: Option<S>,
+    chunk: Vec<S::Item>,
+    max: usize,
+    timeout: Duration,
+    deadline: Option<Sleep>,
+}
+
+// the buffered items are never pinned
+impl<S: Stream + Unpin> Unpin for ChunksTimeout<S> {}
+
+impl<S: Stream + Unpin> Stream for ChunksTimeout<S> {
+    type Item = Vec<S::Item>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        while let Some(stream) = &mut this.stream {
+            match Pin::new(stream).poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    if this.chunk.is_empty() {
+                        this.deadline = Some(sleep_until(Instant::now() + this.timeout));
+                    }
+                    this.chunk.push(item);
+                    if this.chunk.len() == this.max {
+                        return Poll::Ready(Some(this.take_chunk()));
+                    }
+                }
+                Poll::Ready(None) => this.stream = None,
+                Poll::Pending => break,
+            }
+        }
+
+        if this.stream.is_none() {
+            // flush what's left, then end
+            return match this.chunk.is_empty() {
+                true => Poll::Ready(None),
+                false => Poll::Ready(Some(this.take_chunk())),
+            };
+        }
+
+        let expired = this
+            .deadline
+            .as_mut()
+            .is_some_and(|deadline| Pin::new(deadline).poll(cx).is_ready());
+        match expired {
+            true => Poll::Ready(Some(this.take_chunk())),
+            false => Poll::Pending,
+        }
+    }
+}
+
+impl<S: Stream> ChunksTimeout<S> {
+    fn take_chunk(&mut self) -> Vec<S::Item> {
+        self.deadline = None;
+        std::mem::take(&mut self.chunk)
+    }
+}
+
+pub struct Throttle<S> {
+    stream: S,
+    period: Duration,
+    /// Running while the previous item's period hasn't passed yet.
+    delay: Option<Sleep>,
+}
+
+impl<S: Stream + Unpin> Stream for Throttle<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let this = &mut *self;
+
+        if let Some(delay) = &mut this.delay {
+            if Pin::new(delay).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            this.delay = None;
+        }
+
+        let poll = Pin::new(&mut this.stream).poll_next(cx);
+        if let Poll::Ready(Some(_)) = poll {
+            this.delay = Some(sleep_until(Instant::now() + this.period));
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::executor::{sleep, Builder, UdpSocket};
+
+    #[test]
+    fn adapters_compose() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        let items = executor.block_on(async {
+            let mut stream = iter(1..).filter(|n| n % 2 == 0).map(|n| n * 10).take(3);
+
+            let mut items = Vec::new();
+            while let Some(item) = stream.next().await {
+                items.push(item);
+            }
+            items
+        });
+
+        assert_eq!(items, [20, 40, 60]);
+    }
+
+    #[test]
+    fn buffer_unordered_yields_in_completion_order() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let order = executor.block_on({
+            let (active, peak) = (active.clone(), peak.clone());
+            async move {
+                let mut stream = iter([30u64, 10, 20])
+                    .map(move |delay| {
+                        let (active, peak) = (active.clone(), peak.clone());
+                        Box::pin(async move {
+                            let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                            peak.fetch_max(now, Ordering::SeqCst);
+                            sleep(Duration::from_millis(delay)).await;
+                            active.fetch_sub(1, Ordering::SeqCst);
+                            delay
+                        })
+                    })
+                    .buffer_unordered(3);
+
+                let mut order = Vec::new();
+                while let Some(delay) = stream.next().await {
+                    order.push(delay);
+                }
+                order
+            }
+        });
+
+        assert_eq!(order, [10, 20, 30]);
+        // all three ran at once
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn chunks_are_cut_by_size_or_by_time() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let chunks = executor.block_on(async move {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let addr = socket.local_addr().unwrap();
+
+            spawner.spawn(async move {
+                let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+                for burst in [&[1u8, 2, 3, 4, 5][..], &[6]] {
+                    for n in burst {
+                        sender.send_to(&[*n], addr).await.unwrap();
+                    }
+                    sleep(Duration::from_millis(40)).await;
+                }
+            });
+
+            let mut chunks = socket
+                .incoming()
+                .map(|datagram| datagram.unwrap().0[0])
+                .chunks_timeout(3, Duration::from_millis(20));
+
+            let mut received = Vec::new();
+            for _ in 0..3 {
+                received.push(chunks.next().await.unwrap());
+            }
+            received
+        });
+
+        assert_eq!(chunks, [vec![1, 2, 3], vec![4, 5], vec![6]]);
+    }
+
+    #[test]
+    fn throttle_spaces_out_items() {
+        let (executor, _spawner) = Builder::new().reactor_thread().build().unwrap();
+
+        let elapsed = executor.block_on(async {
+            let start = Instant::now();
+            let mut stream = iter(0..4).throttle(Duration::from_millis(15));
+            while stream.next().await.is_some() {}
+            start.elapsed()
+        });
+
+        // the first item is immediate, the next three wait a period each
+        assert!(elapsed >= Duration::from_millis(45), "{elapsed:?}");
+    }
+}
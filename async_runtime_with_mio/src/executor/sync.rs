@@ -0,0 +1,687 @@
+//! Synchronisation primitives for tasks running on the executor.
+
+pub mod oneshot {
+    //! A channel carrying a single value from one task to another.
+
+    use std::{
+        fmt,
+        future::Future,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll, Waker},
+    };
+
+    use crate::executor::lock;
+
+    /// The sender went away without sending a value.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Canceled;
+
+    impl fmt::Display for Canceled {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("oneshot sender dropped")
+        }
+    }
+
+    impl std::error::Error for Canceled {}
+
+    struct State<T> {
+        value: Option<T>,
+        closed: bool,
+        waker: Option<Waker>,
+    }
+
+    pub struct Sender<T> {
+        state: Arc<Mutex<State<T>>>,
+    }
+
+    /// Awaiting the receiver yields the value, or `Canceled` if the
+    /// sender was dropped first.
+    pub struct Receiver<T> {
+        state: Arc<Mutex<State<T>>>,
+    }
+
+    pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+        let state = Arc::new(Mutex::new(State {
+            value: None,
+            closed: false,
+            waker: None,
+        }));
+        (
+            Sender {
+                state: state.clone(),
+            },
+            Receiver { state },
+        )
+    }
+
+    impl<T> Sender<T> {
+        /// Hands `value` to the receiver, or back if the receiver is gone.
+        pub fn send(self, value: T) -> Result<(), T> {
+            let mut state = lock(&self.state);
+            if state.closed {
+                return Err(value);
+            }
+            state.value = Some(value);
+            Ok(())
+            // the waker fires when `self` drops
+        }
+
+        pub fn is_closed(&self) -> bool {
+            lock(&self.state).closed
+        }
+    }
+
+    impl<T> Drop for Sender<T> {
+        fn drop(&mut self) {
+            let mut state = lock(&self.state);
+            state.closed = true;
+            let waker = state.waker.take();
+            std::mem::drop(state);
+
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+
+    impl<T> Future for Receiver<T> {
+        type Output = Result<T, Canceled>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let mut state = lock(&self.state);
+
+            if let Some(value) = state.value.take() {
+                return Poll::Ready(Ok(value));
+            }
+            if state.closed {
+                return Poll::Ready(Err(Canceled));
+            }
+
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    impl<T> Drop for Receiver<T> {
+        fn drop(&mut self) {
+            lock(&self.state).closed = true;
+        }
+    }
+}
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use super::{
+    lock,
+    time::{timeout, Elapsed},
+};
+
+/// Limits how many tasks may hold a permit at once.
+///
+/// Waiters are served first come, first served.
+pub struct Semaphore {
+    state: Mutex<SemaphoreState>,
+}
+
+struct SemaphoreState {
+    permits: usize,
+    waiters: VecDeque<(u64, Waker)>,
+    next_waiter: u64,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Semaphore {
+            state: Mutex::new(SemaphoreState {
+                permits,
+                waiters: VecDeque::new(),
+                next_waiter: 0,
+            }),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        lock(&self.state).permits
+    }
+
+    /// Waits for a permit, which is given back when the guard drops.
+    pub fn acquire(&self) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            waiter: None,
+        }
+    }
+
+    /// Like `acquire`, for a semaphore shared through an `Arc`, so the
+    /// permit can outlive the borrow.
+    pub async fn acquire_owned(self: Arc<Self>) -> OwnedPermit {
+        self.acquire().await.forget();
+        OwnedPermit { semaphore: self }
+    }
+
+    fn release(&self) {
+        let mut state = lock(&self.state);
+        state.permits += 1;
+        let next = state.waiters.front().map(|(_, waker)| waker.clone());
+        std::mem::drop(state);
+
+        if let Some(waker) = next {
+            waker.wake();
+        }
+    }
+}
+
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    waiter: Option<u64>,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = Permit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Permit<'a>> {
+        let semaphore = self.semaphore;
+        let mut state = lock(&semaphore.state);
+
+        let first = match (state.waiters.front(), self.waiter) {
+            (None, _) => true,
+            (Some((front, _)), Some(waiter)) => *front == waiter,
+            (Some(_), None) => false,
+        };
+
+        if state.permits > 0 && first {
+            state.permits -= 1;
+            if self.waiter.take().is_some() {
+                state.waiters.pop_front();
+            }
+
+            // several releases may have woken only us: pass the rest on
+            let next = match (state.permits, state.waiters.front()) {
+                (1.., Some((_, waker))) => Some(waker.clone()),
+                _ => None,
+            };
+            std::mem::drop(state);
+
+            if let Some(waker) = next {
+                waker.wake();
+            }
+            return Poll::Ready(Permit { semaphore });
+        }
+
+        match self.waiter {
+            Some(waiter) => {
+                let entry = state.waiters.iter_mut().find(|(id, _)| *id == waiter);
+                if let Some((_, waker)) = entry {
+                    waker.clone_from(cx.waker());
+                }
+            }
+            None => {
+                let waiter = state.next_waiter;
+                state.next_waiter += 1;
+                state.waiters.push_back((waiter, cx.waker().clone()));
+                self.waiter = Some(waiter);
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        let Some(waiter) = self.waiter else {
+            return;
+        };
+
+        let mut state = lock(&self.semaphore.state);
+        let was_first = state.waiters.front().is_some_and(|(id, _)| *id == waiter);
+        state.waiters.retain(|(id, _)| *id != waiter);
+
+        // we may have been woken for a permit we'll never take: pass it on
+        let next = match (was_first, state.permits, state.waiters.front()) {
+            (true, 1.., Some((_, waker))) => Some(waker.clone()),
+            _ => None,
+        };
+        std::mem::drop(state);
+
+        if let Some(waker) = next {
+            waker.wake();
+        }
+    }
+}
+
+/// Holds one permit of a `Semaphore` until dropped.
+pub struct Permit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Permit<'_> {
+    /// Keeps the permit taken without holding on to the guard.
+    fn forget(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.release();
+    }
+}
+
+/// A `Permit` that owns a reference to its `Semaphore`.
+pub struct OwnedPermit {
+    semaphore: Arc<Semaphore>,
+}
+
+impl Drop for OwnedPermit {
+    fn drop(&mut self) {
+        self.semaphore.release();
+    }
+}
+
+/// Holds tasks until `n` of them are waiting, then releases them all.
+///
+/// The barrier can be reused: once released, the next `n` waiters form a
+/// new batch.
+pub struct Barrier {
+    n: usize,
+    state: Mutex<BarrierState>,
+}
+
+struct BarrierState {
+    arrived: usize,
+    // bumped every time a batch is released
+    generation: u64,
+    waiters: HashMap<u64, Waker>,
+    next_waiter: u64,
+}
+
+/// Tells apart the one task that completed a batch, like
+/// `std::sync::BarrierWaitResult`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult {
+    leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// True for the last task to arrive, and for no other in its batch.
+    pub fn is_leader(&self) -> bool {
+        self.leader
+    }
+}
+
+impl Barrier {
+    /// A barrier for batches of `n` tasks; 0 behaves like 1.
+    pub fn new(n: usize) -> Self {
+        Barrier {
+            n: n.max(1),
+            state: Mutex::new(BarrierState {
+                arrived: 0,
+                generation: 0,
+                waiters: HashMap::new(),
+                next_waiter: 0,
+            }),
+        }
+    }
+
+    /// Waits until the batch is complete.
+    ///
+    /// Dropping the future before then withdraws the task from the batch.
+    pub fn wait(&self) -> BarrierWait<'_> {
+        BarrierWait {
+            barrier: self,
+            arrival: None,
+        }
+    }
+
+    /// Like `wait`, giving up, and leaving the batch, after `duration`.
+    pub async fn wait_timeout(&self, duration: Duration) -> Result<BarrierWaitResult, Elapsed> {
+        timeout(duration, self.wait()).await
+    }
+}
+
+pub struct BarrierWait<'a> {
+    barrier: &'a Barrier,
+    // the generation it arrived in and its waiter id, until released
+    arrival: Option<(u64, u64)>,
+}
+
+impl Future for BarrierWait<'_> {
+    type Output = BarrierWaitResult;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<BarrierWaitResult> {
+        let barrier = self.barrier;
+        let mut state = lock(&barrier.state);
+
+        match self.arrival {
+            Some((generation, _)) if generation != state.generation => {
+                self.arrival = None;
+                Poll::Ready(BarrierWaitResult { leader: false })
+            }
+            Some((_, waiter)) => {
+                if let Some(waker) = state.waiters.get_mut(&waiter) {
+                    waker.clone_from(cx.waker());
+                }
+                Poll::Pending
+            }
+            None if state.arrived + 1 == barrier.n => {
+                state.arrived = 0;
+                state.generation += 1;
+                let waiters = std::mem::take(&mut state.waiters);
+                std::mem::drop(state);
+
+                waiters.into_values().for_each(Waker::wake);
+                Poll::Ready(BarrierWaitResult { leader: true })
+            }
+            None => {
+                state.arrived += 1;
+                let waiter = state.next_waiter;
+                state.next_waiter += 1;
+                state.waiters.insert(waiter, cx.waker().clone());
+                self.arrival = Some((state.generation, waiter));
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for BarrierWait<'_> {
+    fn drop(&mut self) {
+        let Some((generation, waiter)) = self.arrival else {
+            return;
+        };
+
+        // released but never polled again: nothing to withdraw
+        let mut state = lock(&self.barrier.state);
+        if state.generation == generation {
+            state.arrived -= 1;
+            state.waiters.remove(&waiter);
+        }
+    }
+}
+
+/// Opens once `count_down` has been called `count` times, releasing every
+/// task waiting for it. A latch opens once and stays open.
+pub struct CountdownLatch {
+    state: Mutex<LatchState>,
+}
+
+struct LatchState {
+    count: usize,
+    waiters: HashMap<u64, Waker>,
+    next_waiter: u64,
+}
+
+impl CountdownLatch {
+    pub fn new(count: usize) -> Self {
+        CountdownLatch {
+            state: Mutex::new(LatchState {
+                count,
+                waiters: HashMap::new(),
+                next_waiter: 0,
+            }),
+        }
+    }
+
+    /// How many `count_down`s are still missing.
+    pub fn count(&self) -> usize {
+        lock(&self.state).count
+    }
+
+    /// Counts one down, opening the latch if that was the last one. Does
+    /// nothing on an open latch.
+    pub fn count_down(&self) {
+        let mut state = lock(&self.state);
+        if state.count == 0 {
+            return;
+        }
+        state.count -= 1;
+        if state.count > 0 {
+            return;
+        }
+        let waiters = std::mem::take(&mut state.waiters);
+        std::mem::drop(state);
+
+        waiters.into_values().for_each(Waker::wake);
+    }
+
+    /// Waits until the latch is open.
+    pub fn wait(&self) -> LatchWait<'_> {
+        LatchWait {
+            latch: self,
+            waiter: None,
+        }
+    }
+
+    /// Like `wait`, giving up after `duration`.
+    pub async fn wait_timeout(&self, duration: Duration) -> Result<(), Elapsed> {
+        timeout(duration, self.wait()).await
+    }
+}
+
+pub struct LatchWait<'a> {
+    latch: &'a CountdownLatch,
+    waiter: Option<u64>,
+}
+
+impl Future for LatchWait<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = lock(&self.latch.state);
+        if state.count == 0 {
+            // the waker went with the others when the latch opened
+            self.waiter = None;
+            return Poll::Ready(());
+        }
+
+        match self.waiter {
+            Some(waiter) => {
+                if let Some(waker) = state.waiters.get_mut(&waiter) {
+                    waker.clone_from(cx.waker());
+                }
+            }
+            None => {
+                let waiter = state.next_waiter;
+                state.next_waiter += 1;
+                state.waiters.insert(waiter, cx.waker().clone());
+                self.waiter = Some(waiter);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for LatchWait<'_> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter {
+            lock(&self.latch.state).waiters.remove(&waiter);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::executor::{sleep, Builder};
+
+    #[test]
+    fn oneshot_delivers_across_tasks() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let value = executor.block_on(async move {
+            let (sender, receiver) = oneshot::channel();
+            spawner.spawn(async move {
+                sleep(Duration::from_millis(5)).await;
+                sender.send("ready").unwrap();
+            });
+            receiver.await
+        });
+
+        assert_eq!(value, Ok("ready"));
+    }
+
+    #[test]
+    fn oneshot_reports_a_dropped_sender() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        let value = executor.block_on(async {
+            let (sender, receiver) = oneshot::channel::<u8>();
+            std::mem::drop(sender);
+            receiver.await
+        });
+
+        assert_eq!(value, Err(oneshot::Canceled));
+    }
+
+    #[test]
+    fn semaphore_bounds_concurrency() {
+        let (executor, spawner) = Builder::new().reactor_thread().build().unwrap();
+        let semaphore = Arc::new(Semaphore::new(2));
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..8 {
+            let (semaphore, active, peak) = (semaphore.clone(), active.clone(), peak.clone());
+            spawner.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                sleep(Duration::from_millis(5)).await;
+                active.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+        std::mem::drop(spawner);
+        executor.run();
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn back_to_back_releases_reach_every_waiter() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let acquired = executor.block_on(async move {
+            let semaphore = Arc::new(Semaphore::new(0));
+            let acquired = Arc::new(AtomicUsize::new(0));
+
+            for _ in 0..2 {
+                let (semaphore, acquired) = (semaphore.clone(), acquired.clone());
+                spawner.spawn(async move {
+                    semaphore.acquire().await.forget();
+                    acquired.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+            sleep(Duration::from_millis(5)).await;
+
+            // both wake the front waiter, before it gets to run
+            semaphore.release();
+            semaphore.release();
+            sleep(Duration::from_millis(5)).await;
+            acquired.load(Ordering::SeqCst)
+        });
+
+        assert_eq!(acquired, 2);
+    }
+
+    #[test]
+    fn a_barrier_releases_each_batch_together() {
+        let (executor, spawner) = Builder::new().reactor_thread().build().unwrap();
+        let barrier = Arc::new(Barrier::new(4));
+        let checkpoint = Arc::new(AtomicUsize::new(0));
+        let leaders = Arc::new(AtomicUsize::new(0));
+
+        for probe in 0..8u64 {
+            let (barrier, checkpoint, leaders) =
+                (barrier.clone(), checkpoint.clone(), leaders.clone());
+            spawner.spawn(async move {
+                sleep(Duration::from_millis(probe * 2)).await;
+                checkpoint.fetch_add(1, Ordering::SeqCst);
+
+                let released = barrier.wait().await;
+                // at least a whole batch got here first
+                let reached = checkpoint.load(Ordering::SeqCst);
+                assert!(reached >= 4, "{probe}: {reached}");
+                if released.is_leader() {
+                    leaders.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        }
+        std::mem::drop(spawner);
+        executor.run();
+
+        assert_eq!(checkpoint.load(Ordering::SeqCst), 8);
+        assert_eq!(leaders.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn a_barrier_timeout_leaves_the_batch() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let (late, batch) = executor.block_on(async move {
+            let barrier = Arc::new(Barrier::new(2));
+
+            let late = barrier.wait_timeout(Duration::from_millis(10)).await;
+
+            // had the timed out task stayed, this one would go through alone
+            let (sender, receiver) = oneshot::channel();
+            let other = barrier.clone();
+            spawner.spawn(async move {
+                let result = other.wait_timeout(Duration::from_secs(5)).await;
+                let _ = sender.send(result);
+            });
+            sleep(Duration::from_millis(10)).await;
+            let mine = barrier.wait_timeout(Duration::from_secs(5)).await.unwrap();
+            let theirs = receiver.await.unwrap().unwrap();
+
+            (late, [mine.is_leader(), theirs.is_leader()])
+        });
+
+        assert_eq!(late, Err(Elapsed));
+        assert_eq!(batch, [true, false]);
+    }
+
+    #[test]
+    fn a_latch_opens_after_the_last_count_down() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let (early, opened, remaining) = executor.block_on(async move {
+            let latch = Arc::new(CountdownLatch::new(3));
+
+            for probe in 1..=3 {
+                let latch = latch.clone();
+                spawner.spawn(async move {
+                    sleep(Duration::from_millis(probe * 5)).await;
+                    latch.count_down();
+                });
+            }
+
+            let early = latch.wait_timeout(Duration::from_millis(7)).await;
+            let remaining = latch.count();
+            let opened = latch.wait_timeout(Duration::from_secs(5)).await;
+
+            // an open latch lets everyone through, and stays open
+            latch.count_down();
+            latch.wait().await;
+            (early, opened, remaining)
+        });
+
+        assert_eq!(early, Err(Elapsed));
+        assert_eq!(remaining, 2);
+        assert_eq!(opened, Ok(()));
+    }
+}
@@ -0,0 +1,430 @@
+use std::{
+    fmt,
+    future::Future,
+    io,
+    pin::{pin, Pin},
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use super::{
+    reactor::{Reactor, TimerKey},
+    stream::Stream,
+};
+
+/// Completes once `duration` has passed.
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+/// Completes once `deadline` has been reached.
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        reactor: Reactor::get(),
+        deadline,
+        key: None,
+    }
+}
+
+/// The future returned by `sleep` and `sleep_until`.
+///
+/// The timer is only armed in the reactor while the future is being
+/// awaited, and is disarmed again when it is dropped.
+pub struct Sleep {
+    reactor: Arc<Reactor>,
+    deadline: Instant,
+    key: Option<TimerKey>,
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn is_elapsed(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// Moves the deadline, re-arming the timer if it already fired.
+    pub fn reset(&mut self, deadline: Instant) {
+        self.disarm();
+        self.deadline = deadline;
+    }
+
+    fn disarm(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.reactor.remove_timer(key);
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_elapsed() {
+            self.disarm();
+            return Poll::Ready(());
+        }
+
+        match self.key {
+            // still armed: just make sure the right task gets woken
+            Some(key) if self.reactor.update_timer(key, cx.waker()) => {}
+            // fired early (the driver rounds to milliseconds): arm again
+            _ => {
+                let key = self.reactor.insert_timer(self.deadline, cx.waker().clone());
+                self.key = Some(key);
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.disarm();
+    }
+}
+
+/// The error returned by `timeout` when the deadline passes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+impl From<Elapsed> for io::Error {
+    fn from(elapsed: Elapsed) -> Self {
+        io::Error::new(io::ErrorKind::TimedOut, elapsed)
+    }
+}
+
+/// Runs `future` for at most `duration`.
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, Elapsed> {
+    timeout_at(Instant::now() + duration, future).await
+}
+
+/// Runs `future` until `deadline` at the latest.
+pub async fn timeout_at<F: Future>(deadline: Instant, future: F) -> Result<F::Output, Elapsed> {
+    let mut future = pin!(future);
+    let mut sleep = sleep_until(deadline);
+
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        Pin::new(&mut sleep).poll(cx).map(|()| Err(Elapsed))
+    })
+    .await
+}
+
+/// What an `Interval` does when ticks were missed because the task
+/// didn't get to call `tick` in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissedTickBehavior {
+    /// Fire the missed ticks back to back until caught up, keeping the
+    /// original schedule.
+    #[default]
+    Burst,
+    /// Fire once now and restart the schedule from here.
+    Delay,
+    /// Fire once now and drop the missed ticks, staying on the original
+    /// schedule.
+    Skip,
+}
+
+/// Ticks every `period`, starting right away.
+pub fn interval(period: Duration) -> Interval {
+    interval_at(Instant::now(), period)
+}
+
+/// Ticks every `period`, starting at `start`.
+pub fn interval_at(start: Instant, period: Duration) -> Interval {
+    assert!(!period.is_zero(), "an interval's period must be non-zero");
+
+    Interval {
+        period,
+        behavior: MissedTickBehavior::default(),
+        sleep: Some(sleep_until(start)),
+    }
+}
+
+/// `nanos` as a `Duration`, or `None` past `Duration::MAX`.
+pub(crate) fn duration_from_nanos(nanos: u128) -> Option<Duration> {
+    const NANOS_PER_SEC: u128 = 1_000_000_000;
+    let secs = u64::try_from(nanos / NANOS_PER_SEC).ok()?;
+    Some(Duration::new(secs, (nanos % NANOS_PER_SEC) as u32))
+}
+
+pub struct Interval {
+    period: Duration,
+    behavior: MissedTickBehavior,
+    /// Sleeps until the next scheduled tick, `None` once that tick is
+    /// later than an `Instant` can hold and never comes.
+    sleep: Option<Sleep>,
+}
+
+impl Interval {
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    pub fn missed_tick_behavior(&self) -> MissedTickBehavior {
+        self.behavior
+    }
+
+    pub fn set_missed_tick_behavior(&mut self, behavior: MissedTickBehavior) {
+        self.behavior = behavior;
+    }
+
+    /// Waits for the next tick, returning the instant it was scheduled for.
+    pub async fn tick(&mut self) -> Instant {
+        std::future::poll_fn(|cx| self.poll_tick(cx)).await
+    }
+
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        let Some(sleep) = &mut self.sleep else {
+            return Poll::Pending;
+        };
+        if Pin::new(&mut *sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let scheduled = sleep.deadline();
+        let now = Instant::now();
+        let on_grid = scheduled.checked_add(self.period);
+        let next = match self.behavior {
+            MissedTickBehavior::Burst => on_grid,
+            MissedTickBehavior::Delay => match on_grid {
+                Some(next) if now > next => now.checked_add(self.period),
+                on_grid => on_grid,
+            },
+            MissedTickBehavior::Skip => {
+                // the first tick on the original grid that is still ahead
+                let behind = now.duration_since(scheduled).as_nanos() / self.period.as_nanos();
+                self.period
+                    .as_nanos()
+                    .checked_mul(behind + 1)
+                    .and_then(duration_from_nanos)
+                    .and_then(|ahead| scheduled.checked_add(ahead))
+            }
+        };
+        match next {
+            Some(next) => sleep.reset(next),
+            None => self.sleep = None,
+        }
+
+        Poll::Ready(scheduled)
+    }
+
+    /// Restarts the schedule, with the next tick one period from now.
+    pub fn reset(&mut self) {
+        self.sleep = Instant::now().checked_add(self.period).map(sleep_until);
+    }
+}
+
+impl Stream for Interval {
+    type Item = Instant;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Instant>> {
+        self.get_mut().poll_tick(cx).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{Builder, DriverMode, UdpSocket};
+
+    fn both_modes(test: impl Fn(DriverMode)) {
+        test(DriverMode::ReactorThread);
+        test(DriverMode::CurrentThread);
+    }
+
+    #[test]
+    fn sleep_waits_at_least_its_duration() {
+        both_modes(|mode| {
+            let (executor, _spawner) = Builder::new().mode(mode).build().unwrap();
+
+            let elapsed = executor.block_on(async {
+                let start = Instant::now();
+                sleep(Duration::from_millis(30)).await;
+                start.elapsed()
+            });
+
+            assert!(elapsed >= Duration::from_millis(30), "{elapsed:?}");
+            assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+        });
+    }
+
+    #[test]
+    fn timers_fire_in_deadline_order() {
+        both_modes(|mode| {
+            let (executor, spawner) = Builder::new().mode(mode).build().unwrap();
+            let (sender, receiver) = std::sync::mpsc::channel();
+
+            for delay in [40, 10, 25] {
+                let sender = sender.clone();
+                spawner.spawn(async move {
+                    sleep(Duration::from_millis(delay)).await;
+                    sender.send(delay).unwrap();
+                });
+            }
+            drop((sender, spawner));
+            executor.run();
+
+            assert_eq!(receiver.iter().collect::<Vec<_>>(), [10, 25, 40]);
+        });
+    }
+
+    #[test]
+    fn timeout_gives_up_on_a_silent_socket() {
+        both_modes(|mode| {
+            let (executor, _spawner) = Builder::new().mode(mode).build().unwrap();
+
+            let result = executor.block_on(async {
+                let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+                let mut buf = [0; 8];
+                timeout(Duration::from_millis(20), socket.recv_from(&mut buf))
+                    .await
+                    .map(|_| ())
+            });
+
+            assert_eq!(result, Err(Elapsed));
+        });
+    }
+
+    #[test]
+    fn timeout_returns_the_output_when_in_time() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        let result =
+            executor.block_on(async { timeout(Duration::from_secs(5), async { 7 }).await });
+
+        assert_eq!(result, Ok(7));
+    }
+
+    /// Runs an interval whose task is busy for `stall` after the first
+    /// tick, and returns the offsets from the start of the next ticks.
+    fn ticks_after_a_stall(behavior: MissedTickBehavior) -> Vec<u128> {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        executor.block_on(async move {
+            let start = Instant::now();
+            let mut interval = interval_at(start, Duration::from_millis(20));
+            interval.set_missed_tick_behavior(behavior);
+
+            interval.tick().await;
+            // blocks the executor, as a long job would
+            std::thread::sleep(Duration::from_millis(50));
+
+            let mut offsets = Vec::new();
+            for _ in 0..3 {
+                let scheduled = interval.tick().await;
+                offsets.push(scheduled.duration_since(start).as_millis());
+            }
+            offsets
+        })
+    }
+
+    #[test]
+    fn missed_ticks_burst_to_catch_up() {
+        // the instants the ticks were due, however late they fired
+        assert_eq!(ticks_after_a_stall(MissedTickBehavior::Burst), [20, 40, 60]);
+    }
+
+    #[test]
+    fn missed_ticks_delay_the_schedule() {
+        let offsets = ticks_after_a_stall(MissedTickBehavior::Delay);
+
+        assert_eq!(offsets[0], 20);
+        // restarted a period after the late tick fired, at 50ms or later
+        assert!(offsets[1] >= 70, "{offsets:?}");
+        assert!(offsets[2] >= offsets[1] + 20, "{offsets:?}");
+    }
+
+    #[test]
+    fn missed_ticks_are_skipped() {
+        let offsets = ticks_after_a_stall(MissedTickBehavior::Skip);
+
+        assert_eq!(offsets[0], 20);
+        // back on the original grid, past the ticks missed during the stall
+        assert!(offsets[1] >= 60, "{offsets:?}");
+        assert!(offsets[2] > offsets[1], "{offsets:?}");
+        assert!(
+            offsets[1..].iter().all(|offset| offset % 20 == 0),
+            "{offsets:?}"
+        );
+    }
+
+    #[test]
+    fn an_interval_stops_before_ticks_an_instant_cannot_hold() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        for behavior in [
+            MissedTickBehavior::Burst,
+            MissedTickBehavior::Delay,
+            MissedTickBehavior::Skip,
+        ] {
+            let next = executor.block_on(async move {
+                let mut interval = interval_at(Instant::now(), Duration::MAX);
+                interval.set_missed_tick_behavior(behavior);
+                interval.tick().await;
+                timeout(Duration::from_millis(10), interval.tick()).await
+            });
+
+            assert_eq!(next, Err(Elapsed), "{behavior:?}");
+        }
+    }
+
+    #[test]
+    fn an_interval_is_a_stream_of_ticks() {
+        let (executor, _spawner) = Builder::new().reactor_thread().build().unwrap();
+
+        let elapsed = executor.block_on(async {
+            let start = Instant::now();
+            let ticks: Vec<Instant> = {
+                let mut ticks = interval(Duration::from_millis(10)).take(4);
+                let mut all = Vec::new();
+                while let Some(tick) = ticks.next().await {
+                    all.push(tick);
+                }
+                all
+            };
+            assert!(ticks
+                .windows(2)
+                .all(|pair| pair[1] - pair[0] == Duration::from_millis(10)));
+            start.elapsed()
+        });
+
+        // the first tick is immediate
+        assert!(elapsed >= Duration::from_millis(30), "{elapsed:?}");
+    }
+
+    #[test]
+    fn dropped_sleeps_disarm_their_timer() {
+        let (executor, _spawner) = Builder::new().current_thread().build().unwrap();
+
+        executor.block_on(async {
+            let mut early = sleep(Duration::from_secs(60));
+            std::future::poll_fn(|cx| {
+                assert!(Pin::new(&mut early).poll(cx).is_pending());
+                Poll::Ready(())
+            })
+            .await;
+            drop(early);
+
+            // with the 60s timer gone this returns promptly
+            let start = Instant::now();
+            sleep(Duration::from_millis(5)).await;
+            assert!(start.elapsed() < Duration::from_secs(1));
+        });
+    }
+}
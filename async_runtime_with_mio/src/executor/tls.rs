@@ -0,0 +1,302 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::Shutdown,
+    sync::Arc,
+};
+
+use rustls::{
+    pki_types::ServerName, ClientConfig, ClientConnection, ServerConfig, ServerConnection,
+};
+
+use super::{reactor::Direction, TcpStream};
+
+/// A TLS session running over one of the runtime's `TcpStream`s.
+///
+/// rustls does no I/O itself: it only turns plaintext into TLS records and
+/// back. This wrapper moves those records over the socket, waiting on the
+/// reactor whenever the socket would block.
+pub struct TlsStream {
+    io: TcpStream,
+    conn: rustls::Connection,
+}
+
+impl TlsStream {
+    /// Performs a client handshake for `server_name` over `io`.
+    pub async fn connect(
+        config: Arc<ClientConfig>,
+        server_name: &str,
+        io: TcpStream,
+    ) -> io::Result<Self> {
+        let server_name = ServerName::try_from(server_name.to_owned())
+            .map_err(|error| io::Error::new(ErrorKind::InvalidInput, error))?;
+        let conn = ClientConnection::new(config, server_name).map_err(io::Error::other)?;
+
+        let mut stream = TlsStream {
+            io,
+            conn: conn.into(),
+        };
+        stream.handshake().await?;
+
+        Ok(stream)
+    }
+
+    /// Performs a server handshake over an accepted `io`.
+    pub async fn accept(config: Arc<ServerConfig>, io: TcpStream) -> io::Result<Self> {
+        let conn = ServerConnection::new(config).map_err(io::Error::other)?;
+
+        let mut stream = TlsStream {
+            io,
+            conn: conn.into(),
+        };
+        stream.handshake().await?;
+
+        Ok(stream)
+    }
+
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.io
+    }
+
+    /// The negotiated ALPN protocol, if any.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.conn.alpn_protocol()
+    }
+
+    /// The name the client asked for, on the server side of a session.
+    pub fn server_name(&self) -> Option<&str> {
+        match &self.conn {
+            rustls::Connection::Server(conn) => conn.server_name(),
+            rustls::Connection::Client(_) => None,
+        }
+    }
+
+    async fn handshake(&mut self) -> io::Result<()> {
+        while self.conn.is_handshaking() {
+            if self.conn.wants_write() {
+                self.flush_tls().await?;
+            } else if self.conn.wants_read() && self.read_tls().await? == 0 {
+                return Err(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "connection closed during the tls handshake",
+                ));
+            }
+        }
+
+        // the last handshake flight may still be buffered
+        self.flush_tls().await
+    }
+
+    /// Writes every buffered TLS record to the socket.
+    async fn flush_tls(&mut self) -> io::Result<()> {
+        let TlsStream { io, conn } = self;
+
+        while conn.wants_write() {
+            io.io(Direction::Write, |mut stream| conn.write_tls(&mut stream))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Reads TLS records from the socket and decrypts them.
+    ///
+    /// Returns the number of bytes read from the socket, 0 meaning the peer
+    /// closed it.
+    async fn read_tls(&mut self) -> io::Result<usize> {
+        let TlsStream { io, conn } = self;
+
+        let n = io
+            .io(Direction::Read, |mut stream| conn.read_tls(&mut stream))
+            .await?;
+
+        if let Err(error) = conn.process_new_packets() {
+            // try to tell the peer why before giving up
+            let _ = self.flush_tls().await;
+            return Err(io::Error::new(ErrorKind::InvalidData, error));
+        }
+
+        Ok(n)
+    }
+
+    /// Reads decrypted data into `buf`, returning 0 once the peer has sent
+    /// `close_notify`.
+    pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.conn.reader().read(buf) {
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+
+            // key updates and the like may need an answer before more data
+            self.flush_tls().await?;
+            if self.read_tls().await? == 0 {
+                // the reader now reports either a clean close or a truncation
+                return self.conn.reader().read(buf);
+            }
+        }
+    }
+
+    pub async fn read_exact(&mut self, mut buf: &mut [u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read(buf).await? {
+                0 => return Err(ErrorKind::UnexpectedEof.into()),
+                n => buf = &mut buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Encrypts `buf` and sends it.
+    pub async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.conn.writer().write(buf)?;
+        self.flush_tls().await?;
+        Ok(n)
+    }
+
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends `close_notify` and shuts down the write side of the socket.
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        self.conn.send_close_notify();
+        self.flush_tls().await?;
+        self.io.shutdown(Shutdown::Write)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::{
+        pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+        RootCertStore,
+    };
+
+    use super::*;
+    use crate::executor::{Builder, TcpListener};
+
+    /// A self-signed certificate for `localhost` plus matching configs.
+    fn configs() -> (Arc<ServerConfig>, Arc<ClientConfig>) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let cert = CertificateDer::from(certified.cert.der().to_vec());
+        let key =
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+
+        let server = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.clone()], key)
+            .unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert).unwrap();
+        let client = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        (Arc::new(server), Arc::new(client))
+    }
+
+    #[test]
+    fn client_and_server_exchange_data_over_tls() {
+        let (server_config, client_config) = configs();
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let reply = executor.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            spawner.spawn(async move {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let mut tls = TlsStream::accept(server_config, tcp).await.unwrap();
+                assert_eq!(tls.server_name(), Some("localhost"));
+
+                let mut buf = [0; 5];
+                tls.read_exact(&mut buf).await.unwrap();
+                buf.reverse();
+                tls.write_all(&buf).await.unwrap();
+                tls.shutdown().await.unwrap();
+            });
+
+            let tcp = TcpStream::connect(addr).await.unwrap();
+            let mut tls = TlsStream::connect(client_config, "localhost", tcp)
+                .await
+                .unwrap();
+            tls.write_all(b"hello").await.unwrap();
+
+            let mut reply = Vec::new();
+            let mut buf = [0; 8];
+            loop {
+                match tls.read(&mut buf).await.unwrap() {
+                    0 => break reply,
+                    n => reply.extend_from_slice(&buf[..n]),
+                }
+            }
+        });
+
+        assert_eq!(reply, b"olleh");
+    }
+
+    #[test]
+    fn large_payloads_span_many_records() {
+        let (server_config, client_config) = configs();
+        let (executor, spawner) = Builder::new().reactor_thread().build().unwrap();
+        let payload: Vec<u8> = (0..256 * 1024).map(|i| i as u8).collect();
+        let expected = payload.clone();
+
+        let received = executor.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            spawner.spawn(async move {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let mut tls = TlsStream::accept(server_config, tcp).await.unwrap();
+                tls.write_all(&payload).await.unwrap();
+                tls.shutdown().await.unwrap();
+            });
+
+            let tcp = TcpStream::connect(addr).await.unwrap();
+            let mut tls = TlsStream::connect(client_config, "localhost", tcp)
+                .await
+                .unwrap();
+
+            let mut received = Vec::new();
+            let mut buf = [0; 4096];
+            loop {
+                match tls.read(&mut buf).await.unwrap() {
+                    0 => break received,
+                    n => received.extend_from_slice(&buf[..n]),
+                }
+            }
+        });
+
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn a_certificate_for_another_name_is_rejected() {
+        let (server_config, client_config) = configs();
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let result = executor.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            spawner.spawn(async move {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let _ = TlsStream::accept(server_config, tcp).await;
+            });
+
+            let tcp = TcpStream::connect(addr).await.unwrap();
+            TlsStream::connect(client_config, "gateway.example", tcp)
+                .await
+                .map(drop)
+        });
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
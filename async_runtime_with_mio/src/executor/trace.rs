@@ -0,0 +1,167 @@
+//! Structured events for debugging the runtime, through `tracing`.
+//!
+//! With the `tracing` feature the executor reports tasks being spawned,
+//! polled, woken and completed, the reactor reports registrations and the
+//! events it delivers, and I/O types report the errors they return. Task
+//! events carry `task.id` and `task.name`, reactor events the source's
+//! `token`; targets are the emitting modules, e.g.
+//! `RUST_LOG=async_runtime_with_mio::executor::reactor=trace`.
+//!
+//! Without the feature every event compiles to nothing, and `TaskMeta` has
+//! no size.
+
+/// Emits a `tracing` event at `$level`: `trace`, `debug`, `info`, `warn`
+/// or `error`. Must be used as a statement.
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::$level!($($arg)+);
+    };
+}
+
+/// An `event!` about the task described by `$meta`.
+macro_rules! task_event {
+    ($level:ident, $meta:expr, $($arg:tt)+) => {
+        $crate::executor::trace::event!(
+            $level,
+            task.id = $meta.id,
+            task.name = $meta.name,
+            $($arg)+
+        )
+    };
+}
+
+pub(crate) use {event, task_event};
+
+/// What events say about a task.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TaskMeta {
+    /// Unique in the process, in spawn order.
+    #[cfg(feature = "tracing")]
+    pub(crate) id: u64,
+    /// Given to `Spawner::spawn_named`, or else the future's type.
+    #[cfg(feature = "tracing")]
+    pub(crate) name: &'static str,
+}
+
+impl TaskMeta {
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn new(name: &'static str) -> Self {
+        #[cfg(feature = "tracing")]
+        {
+            use std::sync::atomic::{AtomicU64, Ordering};
+
+            static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+            TaskMeta {
+                id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                name,
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        TaskMeta {}
+    }
+}
+
+// tasks pay nothing for their metadata when there are no events
+#[cfg(not(feature = "tracing"))]
+const _: () = assert!(std::mem::size_of::<TaskMeta>() == 0);
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::{
+        fmt::Write,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{field::Visit, Subscriber};
+    use tracing_subscriber::{layer::Context, prelude::*, Layer};
+
+    use crate::executor::{Builder, UdpSocket};
+
+    /// Records every event as `message key=value...`.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    struct Line(String);
+
+    impl Visit for Line {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            match field.name() {
+                "message" => self.0.insert_str(0, &format!("{value:?}")),
+                name => write!(self.0, " {name}={value:?}").unwrap(),
+            }
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for Recorder {
+        fn on_event(&self, event: &tracing::Event<'_>, _: Context<'_, S>) {
+            let mut line = Line(String::new());
+            event.record(&mut line);
+            self.0.lock().unwrap().push(line.0);
+        }
+    }
+
+    impl Recorder {
+        fn lines(&self, message: &str) -> Vec<String> {
+            let lines = self.0.lock().unwrap();
+            lines
+                .iter()
+                .filter(|line| line.starts_with(message))
+                .cloned()
+                .collect()
+        }
+    }
+
+    #[test]
+    fn tasks_and_the_reactor_report_their_lifecycle() {
+        let recorder = Recorder::default();
+        let subscriber = tracing_subscriber::registry().with(recorder.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            // the current-thread driver keeps every event on this thread
+            let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+            executor.block_on(async move {
+                let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+                let addr = socket.local_addr().unwrap();
+
+                spawner.spawn_named("sender", async move {
+                    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+                    sender.send_to(b"ping", addr).await.unwrap();
+                });
+
+                let mut buf = [0; 4];
+                socket.recv_from(&mut buf).await.unwrap();
+            });
+        });
+
+        let spawned = recorder.lines("task spawned");
+        assert!(spawned
+            .iter()
+            .any(|line| line.contains("task.name=\"sender\"")));
+        assert!(spawned
+            .iter()
+            .any(|line| line.contains("task.name=\"block_on\"")));
+
+        let sender = spawned
+            .iter()
+            .find(|line| line.contains("\"sender\""))
+            .unwrap();
+        let id = sender
+            .split(' ')
+            .find(|field| field.starts_with("task.id="))
+            .unwrap();
+        for message in ["polling task", "task completed"] {
+            let lines = recorder.lines(message);
+            let about_sender = |line: &String| line.split(' ').any(|field| field == id);
+            assert!(lines.iter().any(about_sender), "{message}: {lines:?}");
+        }
+        // the receive parked `block_on` until the datagram arrived
+        assert!(!recorder.lines("task woken").is_empty());
+
+        let registered = recorder.lines("source registered");
+        assert_eq!(registered.len(), 2, "{registered:?}");
+        assert!(!recorder.lines("I/O event").is_empty());
+        assert_eq!(recorder.lines("source deregistered").len(), 2);
+    }
+}
@@ -0,0 +1,629 @@
+//! Completion-based I/O through io_uring, for UDP and files.
+//!
+//! Instead of waiting for readiness and then making the syscall, an
+//! operation is handed to the kernel whole and the reactor picks up its
+//! result. The ring's descriptor is registered with `mio::Poll` like any
+//! socket, so either driver waits for completions and readiness at once.
+//!
+//! The kernel writes into an operation's buffers until it completes, so
+//! they belong to the operation rather than to the caller: a future that
+//! is dropped early leaves them with the ring until the completion, or the
+//! cancellation, comes back.
+//!
+//! Receives are the exception: a datagram the kernel has written into an
+//! abandoned buffer would be lost, so an unfinished receive is parked on
+//! its socket and picked up by the next `recv_from` instead.
+
+use std::{
+    any::Any,
+    collections::{HashMap, VecDeque},
+    future::Future,
+    io, mem,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    os::fd::{AsRawFd, RawFd},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll, Waker},
+};
+
+use io_uring::{opcode, squeue, types::Fd, IoUring};
+
+use super::{lock, trace::event};
+
+/// Submission queue size; the completion queue is twice that, and the
+/// kernel holds on to overflowing completions rather than drop them.
+const ENTRIES: u32 = 256;
+
+// `user_data` of cancellations, whose completions nobody waits for
+const CANCEL: u64 = u64::MAX;
+
+pub(crate) struct Uring {
+    ring: Mutex<IoUring>,
+    ops: Mutex<Ops>,
+}
+
+#[derive(Default)]
+struct Ops {
+    next_id: u64,
+    entries: HashMap<u64, Lifecycle>,
+}
+
+enum Lifecycle {
+    Submitted(Option<Waker>),
+    Completed(i32),
+    // the future was dropped; its buffers wait here for the kernel
+    Abandoned(Box<dyn Any + Send>),
+}
+
+impl Uring {
+    /// Sets up a ring, failing where the kernel has no io_uring or a
+    /// seccomp filter forbids it.
+    pub(crate) fn new() -> io::Result<Self> {
+        Ok(Uring {
+            ring: Mutex::new(IoUring::new(ENTRIES)?),
+            ops: Mutex::new(Ops::default()),
+        })
+    }
+
+    /// Queues `entry` and hands it to the kernel, returning its id.
+    ///
+    /// # Safety
+    ///
+    /// Everything `entry` points to must stay put until its completion has
+    /// been reaped, or the operation has been abandoned with it.
+    unsafe fn submit(&self, entry: squeue::Entry) -> io::Result<u64> {
+        let id = {
+            let mut ops = lock(&self.ops);
+            let id = ops.next_id;
+            ops.next_id += 1;
+            ops.entries.insert(id, Lifecycle::Submitted(None));
+            id
+        };
+
+        let mut ring = lock(&self.ring);
+        let entry = entry.user_data(id);
+        let mut pushed = unsafe { ring.submission().push(&entry) }.map_err(io::Error::other);
+        if pushed.is_err() {
+            // make room by handing the queued entries over
+            pushed = ring
+                .submit()
+                .and_then(|_| unsafe { ring.submission().push(&entry) }.map_err(io::Error::other));
+        }
+        if let Err(error) = pushed {
+            std::mem::drop(ring);
+            lock(&self.ops).entries.remove(&id);
+            return Err(error);
+        }
+
+        // Once queued the entry can't be taken back, so a failed submit is
+        // not reported; the next submit, or the next `reap`, retries it.
+        let _ = ring.submit();
+        event!(
+            trace,
+            op = id,
+            opcode = entry.get_opcode(),
+            "io_uring operation submitted"
+        );
+        Ok(id)
+    }
+
+    /// Resolves with the result of operation `id`, storing the task's waker
+    /// until then.
+    fn poll_result(&self, id: u64, cx: &mut Context<'_>) -> Poll<i32> {
+        let mut ops = lock(&self.ops);
+        match ops.entries.get_mut(&id) {
+            Some(Lifecycle::Submitted(waker)) => {
+                match waker {
+                    Some(waker) if waker.will_wake(cx.waker()) => {}
+                    _ => *waker = Some(cx.waker().clone()),
+                }
+                Poll::Pending
+            }
+            Some(Lifecycle::Completed(result)) => {
+                let result = *result;
+                ops.entries.remove(&id);
+                Poll::Ready(result)
+            }
+            Some(Lifecycle::Abandoned(_)) | None => unreachable!("polled an operation it gave up"),
+        }
+    }
+
+    /// Gives up on operation `id`: its buffers are freed once the kernel is
+    /// done with them, which it is asked to hurry up with.
+    fn abandon(&self, id: u64, data: Box<dyn Any + Send>) {
+        let mut ops = lock(&self.ops);
+        if let Some(Lifecycle::Completed(_)) = ops.entries.get(&id) {
+            ops.entries.remove(&id);
+            std::mem::drop(ops);
+            return;
+        }
+        ops.entries.insert(id, Lifecycle::Abandoned(data));
+        std::mem::drop(ops);
+        event!(debug, op = id, "io_uring operation abandoned, canceling it");
+
+        // Best effort: if the ring is full, the operation finishes normally.
+        let cancel = opcode::AsyncCancel::new(id).build().user_data(CANCEL);
+        let mut ring = lock(&self.ring);
+        if unsafe { ring.submission().push(&cancel) }.is_ok() {
+            let _ = ring.submit();
+        }
+    }
+
+    /// Collects the completions posted since the last call, and returns the
+    /// wakers of the tasks waiting for them.
+    pub(crate) fn reap(&self) -> Vec<Waker> {
+        let completions: Vec<_> = {
+            let mut ring = lock(&self.ring);
+            if !ring.submission().is_empty() {
+                let _ = ring.submit();
+            }
+            ring.completion()
+                .map(|cqe| (cqe.user_data(), cqe.result()))
+                .collect()
+        };
+        if completions.is_empty() {
+            return Vec::new();
+        }
+
+        let mut wakers = Vec::new();
+        let mut abandoned = Vec::new();
+        let mut ops = lock(&self.ops);
+
+        for (id, result) in completions {
+            event!(trace, op = id, result, "io_uring operation completed");
+            let Some(op) = ops.entries.get_mut(&id) else {
+                continue;
+            };
+            match mem::replace(op, Lifecycle::Completed(result)) {
+                Lifecycle::Submitted(waker) => wakers.extend(waker),
+                Lifecycle::Abandoned(data) => {
+                    ops.entries.remove(&id);
+                    abandoned.push(data);
+                }
+                Lifecycle::Completed(_) => unreachable!("an operation completes once"),
+            }
+        }
+
+        // buffers are freed outside of the lock
+        std::mem::drop(ops);
+        std::mem::drop(abandoned);
+        wakers
+    }
+
+    /// Drops the wakers of pending operations; see `Reactor::clear`.
+    pub(crate) fn take_wakers(&self) -> Vec<Waker> {
+        lock(&self.ops)
+            .entries
+            .values_mut()
+            .filter_map(|op| match op {
+                Lifecycle::Submitted(waker) => waker.take(),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl AsRawFd for Uring {
+    fn as_raw_fd(&self) -> RawFd {
+        lock(&self.ring).as_raw_fd()
+    }
+}
+
+/// An operation in flight, owning `T`: everything the kernel points into.
+struct Op<T: Send + 'static> {
+    uring: Arc<Uring>,
+    id: u64,
+    // `None` once handed back with the result
+    data: Option<Box<T>>,
+}
+
+impl<T: Send + 'static> Op<T> {
+    /// Submits the entry `build` makes from `data`, whose address no longer
+    /// changes once boxed.
+    ///
+    /// # Safety
+    ///
+    /// The entry must only point into `data`, or into memory that outlives
+    /// the operation.
+    unsafe fn submit(
+        uring: &Arc<Uring>,
+        mut data: Box<T>,
+        build: impl FnOnce(&mut T) -> squeue::Entry,
+    ) -> io::Result<Self> {
+        let id = uring.submit(build(&mut data))?;
+        Ok(Op {
+            uring: uring.clone(),
+            id,
+            data: Some(data),
+        })
+    }
+}
+
+impl<T: Send + 'static> Future for Op<T> {
+    type Output = (io::Result<usize>, Box<T>);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        ready!(super::coop::poll_proceed(cx));
+
+        // Many operations, like most sends, complete while being submitted:
+        // pick them up here instead of waiting for the next reactor turn.
+        for waker in self.uring.reap() {
+            waker.wake();
+        }
+        let result = ready!(self.uring.poll_result(self.id, cx));
+
+        let data = self.data.take().expect("polled after completion");
+        let result = match result {
+            result if result < 0 => Err(io::Error::from_raw_os_error(-result)),
+            result => Ok(result as usize),
+        };
+        #[cfg(feature = "tracing")]
+        if let Err(error) = &result {
+            event!(debug, op = self.id, %error, "I/O error");
+        }
+        Poll::Ready((result, data))
+    }
+}
+
+impl<T: Send + 'static> Drop for Op<T> {
+    fn drop(&mut self) {
+        if let Some(data) = self.data.take() {
+            self.uring.abandon(self.id, data);
+        }
+    }
+}
+
+/// A `sendmsg` or `recvmsg`: the datagram, the peer address and the
+/// headers pointing at both.
+struct Msg {
+    buf: Vec<u8>,
+    addr: libc::sockaddr_storage,
+    iov: libc::iovec,
+    hdr: libc::msghdr,
+}
+
+// The raw pointers only ever point into the same box.
+unsafe impl Send for Msg {}
+
+impl Msg {
+    fn new(buf: Vec<u8>, peer: Option<SocketAddr>) -> Box<Self> {
+        let mut msg = Box::new(Msg {
+            buf,
+            addr: unsafe { mem::zeroed() },
+            iov: unsafe { mem::zeroed() },
+            hdr: unsafe { mem::zeroed() },
+        });
+
+        let addr_len = match peer {
+            Some(peer) => write_sockaddr(&mut msg.addr, peer),
+            None => mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t,
+        };
+        msg.iov = libc::iovec {
+            iov_base: msg.buf.as_mut_ptr().cast(),
+            iov_len: msg.buf.len(),
+        };
+        msg.hdr.msg_name = (&raw mut msg.addr).cast();
+        msg.hdr.msg_namelen = addr_len;
+        msg.hdr.msg_iov = &raw mut msg.iov;
+        msg.hdr.msg_iovlen = 1;
+        msg
+    }
+}
+
+fn write_sockaddr(storage: &mut libc::sockaddr_storage, addr: SocketAddr) -> libc::socklen_t {
+    match addr {
+        SocketAddr::V4(addr) => {
+            let sin = unsafe { &mut *(storage as *mut _ as *mut libc::sockaddr_in) };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = addr.port().to_be();
+            sin.sin_addr.s_addr = u32::from_ne_bytes(addr.ip().octets());
+            mem::size_of::<libc::sockaddr_in>() as libc::socklen_t
+        }
+        SocketAddr::V6(addr) => {
+            let sin6 = unsafe { &mut *(storage as *mut _ as *mut libc::sockaddr_in6) };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = addr.port().to_be();
+            sin6.sin6_flowinfo = addr.flowinfo();
+            sin6.sin6_addr.s6_addr = addr.ip().octets();
+            sin6.sin6_scope_id = addr.scope_id();
+            mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t
+        }
+    }
+}
+
+fn read_sockaddr(storage: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+    match storage.ss_family as libc::c_int {
+        libc::AF_INET => {
+            let sin = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+            Ok(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::from(sin.sin_addr.s_addr.to_ne_bytes()),
+                u16::from_be(sin.sin_port),
+            )))
+        }
+        libc::AF_INET6 => {
+            let sin6 = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(sin6.sin6_addr.s6_addr),
+                u16::from_be(sin6.sin6_port),
+                sin6.sin6_flowinfo,
+                sin6.sin6_scope_id,
+            )))
+        }
+        family => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected address family {family}"),
+        )),
+    }
+}
+
+pub(crate) async fn send_to(
+    uring: &Arc<Uring>,
+    fd: RawFd,
+    buf: &[u8],
+    dest: SocketAddr,
+) -> io::Result<usize> {
+    let msg = Msg::new(buf.to_vec(), Some(dest));
+    let op = unsafe {
+        Op::submit(uring, msg, |msg| {
+            opcode::SendMsg::new(Fd(fd), &msg.hdr).build()
+        })?
+    };
+
+    op.await.0
+}
+
+/// The receives of a socket whose `recv_from` was dropped before they
+/// finished, oldest first.
+#[derive(Default)]
+pub(crate) struct ParkedRecvs(Mutex<VecDeque<Op<Msg>>>);
+
+/// A receive that parks its operation if dropped unfinished.
+struct Recv<'a> {
+    // `None` once finished
+    op: Option<Op<Msg>>,
+    parked: &'a ParkedRecvs,
+}
+
+impl Future for Recv<'_> {
+    type Output = (io::Result<usize>, Box<Msg>);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let op = self.op.as_mut().expect("polled after completion");
+        let output = ready!(Pin::new(op).poll(cx));
+        self.op = None;
+        Poll::Ready(output)
+    }
+}
+
+impl Drop for Recv<'_> {
+    fn drop(&mut self) {
+        if let Some(op) = self.op.take() {
+            lock(&self.parked.0).push_back(op);
+        }
+    }
+}
+
+/// Receives a datagram, resuming a parked receive if there is one. That
+/// receive keeps the buffer size of the call that started it, and like
+/// any receive truncates longer datagrams to `buf`.
+pub(crate) async fn recv_from(
+    uring: &Arc<Uring>,
+    fd: RawFd,
+    buf: &mut [u8],
+    parked: &ParkedRecvs,
+) -> io::Result<(usize, SocketAddr)> {
+    let resumed = lock(&parked.0).pop_front();
+    let op = match resumed {
+        Some(op) => op,
+        None => {
+            let msg = Msg::new(vec![0; buf.len()], None);
+            unsafe {
+                Op::submit(uring, msg, |msg| {
+                    opcode::RecvMsg::new(Fd(fd), &raw mut msg.hdr).build()
+                })?
+            }
+        }
+    };
+
+    let recv = Recv {
+        op: Some(op),
+        parked,
+    };
+    let (result, msg) = recv.await;
+    let amt = result?.min(buf.len());
+    buf[..amt].copy_from_slice(&msg.buf[..amt]);
+    Ok((amt, read_sockaddr(&msg.addr)?))
+}
+
+pub(crate) async fn read_at(
+    uring: &Arc<Uring>,
+    fd: RawFd,
+    buf: &mut [u8],
+    offset: u64,
+) -> io::Result<usize> {
+    let len = buf.len().min(u32::MAX as usize);
+    let data = Box::new(vec![0u8; len]);
+    let op = unsafe {
+        Op::submit(uring, data, |data| {
+            opcode::Read::new(Fd(fd), data.as_mut_ptr(), len as u32)
+                .offset(offset)
+                .build()
+        })?
+    };
+
+    let (result, data) = op.await;
+    let amt = result?;
+    buf[..amt].copy_from_slice(&data[..amt]);
+    Ok(amt)
+}
+
+pub(crate) async fn write_at(
+    uring: &Arc<Uring>,
+    fd: RawFd,
+    buf: &[u8],
+    offset: u64,
+) -> io::Result<usize> {
+    let len = buf.len().min(u32::MAX as usize);
+    let data = Box::new(buf[..len].to_vec());
+    let op = unsafe {
+        Op::submit(uring, data, |data| {
+            opcode::Write::new(Fd(fd), data.as_ptr(), len as u32)
+                .offset(offset)
+                .build()
+        })?
+    };
+
+    op.await.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::executor::{
+        sleep, timeout, Builder, DriverMode, Executor, IoBackend, Reactor, Spawner, UdpSocket,
+    };
+
+    fn uring_executor(mode: DriverMode) -> (Executor, Spawner) {
+        Builder::new()
+            .mode(mode)
+            .io_backend(IoBackend::IoUring)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn the_backend_falls_back_only_without_io_uring() {
+        let available = IoUring::new(2).is_ok();
+        let (executor, _spawner) = uring_executor(DriverMode::CurrentThread);
+
+        let expected = match available {
+            true => IoBackend::IoUring,
+            false => IoBackend::Epoll,
+        };
+        assert_eq!(executor.io_backend(), expected);
+
+        let (executor, _spawner) = Builder::new().build().unwrap();
+        assert_eq!(executor.io_backend(), IoBackend::Epoll);
+    }
+
+    #[test]
+    fn datagrams_round_trip_through_the_ring() {
+        for mode in [DriverMode::ReactorThread, DriverMode::CurrentThread] {
+            let (executor, spawner) = uring_executor(mode);
+
+            let replies = executor.block_on(async move {
+                let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+                let server_addr = server.local_addr().unwrap();
+
+                spawner.spawn(async move {
+                    let mut buf = [0; 64];
+                    loop {
+                        let (amt, from) = server.recv_from(&mut buf).await.unwrap();
+                        buf[..amt].reverse();
+                        server.send_to(&buf[..amt], from).await.unwrap();
+                    }
+                });
+
+                let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+                let client_addr = client.local_addr().unwrap();
+                let mut replies = Vec::new();
+                for message in ["hello", "ring"] {
+                    client
+                        .send_to(message.as_bytes(), server_addr)
+                        .await
+                        .unwrap();
+
+                    let mut buf = [0; 64];
+                    let (amt, from) = timeout(Duration::from_secs(5), client.recv_from(&mut buf))
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    assert_eq!(from, server_addr);
+                    assert_ne!(from, client_addr);
+                    replies.push(String::from_utf8(buf[..amt].to_vec()).unwrap());
+                }
+                replies
+            });
+
+            assert_eq!(replies, ["olleh", "gnir"], "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn a_parked_receive_is_canceled_and_freed_with_its_socket() {
+        let (executor, _spawner) = uring_executor(DriverMode::CurrentThread);
+
+        executor.block_on(async {
+            let Some(uring) = Reactor::get().uring().cloned() else {
+                return;
+            };
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let addr = socket.local_addr().unwrap();
+
+            // nothing arrives, so the receive stays with the kernel
+            let mut buf = [0; 16];
+            let waited = timeout(Duration::from_millis(20), socket.recv_from(&mut buf)).await;
+            assert!(waited.is_err());
+            assert_eq!(lock(&uring.ops).entries.len(), 1);
+
+            // and the next call picks it up
+            socket.send_to(b"after", addr).await.unwrap();
+            let (amt, _) = socket.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..amt], b"after");
+            assert!(lock(&uring.ops).entries.is_empty());
+
+            let waited = timeout(Duration::from_millis(20), socket.recv_from(&mut buf)).await;
+            assert!(waited.is_err());
+            std::mem::drop(socket);
+
+            sleep(Duration::from_millis(20)).await;
+            assert!(lock(&uring.ops).entries.is_empty());
+        });
+    }
+
+    #[test]
+    fn a_datagram_for_a_dropped_receive_goes_to_the_next_one() {
+        let (executor, _spawner) = uring_executor(DriverMode::CurrentThread);
+
+        let received = executor.block_on(async {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let addr = socket.local_addr().unwrap();
+            let sender = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+
+            let mut buf = [0; 16];
+            let mut recv = Box::pin(socket.recv_from(&mut buf));
+            std::future::poll_fn(|cx| {
+                assert!(recv.as_mut().poll(cx).is_pending());
+                Poll::Ready(())
+            })
+            .await;
+
+            // the kernel fills the receive's buffer before it is dropped
+            sender.send_to(b"late", addr).unwrap();
+            sleep(Duration::from_millis(10)).await;
+            std::mem::drop(recv);
+
+            let mut buf = [0; 16];
+            let (amt, _) = timeout(Duration::from_secs(1), socket.recv_from(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            buf[..amt].to_vec()
+        });
+
+        assert_eq!(received, b"late");
+    }
+
+    #[test]
+    fn socket_addresses_round_trip() {
+        for addr in ["127.0.0.1:4000", "[::1]:53", "[fe80::1%2]:8080"] {
+            let addr: SocketAddr = addr.parse().unwrap();
+            let mut storage = unsafe { mem::zeroed() };
+
+            write_sockaddr(&mut storage, addr);
+            assert_eq!(read_sockaddr(&storage).unwrap(), addr);
+        }
+    }
+}
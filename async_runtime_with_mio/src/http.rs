@@ -0,0 +1,1059 @@
+//! A small HTTP/1.1 server and client on top of the `executor` runtime.
+//!
+//! Supports `Content-Length` and chunked bodies in both directions and
+//! keep-alive connections. Routing is exact-path matching.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    io::{self, ErrorKind},
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+};
+
+use crate::executor::{Spawner, TcpListener, TcpStream};
+
+// Requests and responses larger than this are refused rather than buffered.
+const MAX_HEAD: usize = 16 * 1024;
+const MAX_BODY: usize = 8 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Version::Http10 => f.write_str("HTTP/1.0"),
+            Version::Http11 => f.write_str("HTTP/1.1"),
+        }
+    }
+}
+
+impl Version {
+    fn parse(s: &str) -> io::Result<Self> {
+        match s {
+            "HTTP/1.0" => Ok(Version::Http10),
+            "HTTP/1.1" => Ok(Version::Http11),
+            _ => Err(invalid("unsupported http version")),
+        }
+    }
+}
+
+/// Header names are compared case-insensitively; insertion order is kept.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Headers(Vec<(String, String)>);
+
+impl Headers {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.remove(&name);
+        self.0.push((name, value.into()));
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.0.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Whether a comma-separated header contains `token`.
+    fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get(name).is_some_and(|value| {
+            value
+                .split(',')
+                .any(|item| item.trim().eq_ignore_ascii_case(token))
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub version: Version,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn new(method: impl Into<String>, path: impl Into<String>) -> Self {
+        Request {
+            method: method.into(),
+            path: path.into(),
+            version: Version::Http11,
+            headers: Headers::default(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn get(path: impl Into<String>) -> Self {
+        Request::new("GET", path)
+    }
+
+    pub fn post(path: impl Into<String>, body: impl Into<Vec<u8>>) -> Self {
+        Request::new("POST", path).with_body(body)
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    fn keep_alive(&self) -> bool {
+        keep_alive(self.version, &self.headers)
+    }
+}
+
+/// How a response body goes on the wire.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Body {
+    /// Sent with a `Content-Length` header.
+    Full(Vec<u8>),
+    /// Sent with `Transfer-Encoding: chunked`, one chunk per element.
+    Chunked(Vec<Vec<u8>>),
+}
+
+impl Body {
+    /// The whole body, whatever its framing.
+    pub fn bytes(&self) -> Vec<u8> {
+        match self {
+            Body::Full(bytes) => bytes.clone(),
+            Body::Chunked(chunks) => chunks.concat(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Headers,
+    pub body: Body,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Self {
+        Response {
+            status,
+            headers: Headers::default(),
+            body: Body::Full(Vec::new()),
+        }
+    }
+
+    pub fn text(status: u16, text: impl Into<String>) -> Self {
+        Response::new(status)
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_body(text.into())
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Body::Full(body.into());
+        self
+    }
+
+    pub fn with_chunks(mut self, chunks: Vec<Vec<u8>>) -> Self {
+        self.body = Body::Chunked(chunks);
+        self
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        _ => "",
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_owned())
+}
+
+fn keep_alive(version: Version, headers: &Headers) -> bool {
+    match version {
+        Version::Http11 => !headers.has_token("Connection", "close"),
+        Version::Http10 => headers.has_token("Connection", "keep-alive"),
+    }
+}
+
+// Begin Implementing the Parser
+
+/// Parses `Name: value` lines up to the blank line ending a head.
+fn parse_headers<'a>(lines: impl Iterator<Item = &'a str>) -> io::Result<Headers> {
+    let mut headers = Headers::default();
+
+    for line in lines {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("malformed header line"))?;
+        if name.is_empty() || name.ends_with(char::is_whitespace) {
+            return Err(invalid("malformed header name"));
+        }
+        headers.0.push((name.to_owned(), value.trim().to_owned()));
+    }
+
+    Ok(headers)
+}
+
+fn head_lines(head: &[u8]) -> io::Result<std::str::Split<'_, &'static str>> {
+    let head = std::str::from_utf8(head).map_err(|_| invalid("head is not utf-8"))?;
+    Ok(head.split("\r\n"))
+}
+
+/// Parses a request head, without the terminating blank line.
+pub fn parse_request_head(head: &[u8]) -> io::Result<Request> {
+    let mut lines = head_lines(head)?;
+    let request_line = lines.next().ok_or_else(|| invalid("empty request"))?;
+
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(path), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid("malformed request line"));
+    };
+    if method.is_empty() || !path.starts_with('/') {
+        return Err(invalid("malformed request line"));
+    }
+
+    Ok(Request {
+        method: method.to_owned(),
+        path: path.to_owned(),
+        version: Version::parse(version)?,
+        headers: parse_headers(lines)?,
+        body: Vec::new(),
+    })
+}
+
+/// Parses a response head, without the terminating blank line.
+pub fn parse_response_head(head: &[u8]) -> io::Result<(Version, Response)> {
+    let mut lines = head_lines(head)?;
+    let status_line = lines.next().ok_or_else(|| invalid("empty response"))?;
+
+    let mut parts = status_line.splitn(3, ' ');
+    let (Some(version), Some(status)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed status line"));
+    };
+    let status = status
+        .parse()
+        .ok()
+        .filter(|status| (100..1000).contains(status))
+        .ok_or_else(|| invalid("malformed status code"))?;
+
+    let response = Response {
+        status,
+        headers: parse_headers(lines)?,
+        body: Body::Full(Vec::new()),
+    };
+
+    Ok((Version::parse(version)?, response))
+}
+
+/// How the body following a head is delimited.
+#[derive(Debug, PartialEq, Eq)]
+enum Framing {
+    Length(usize),
+    Chunked,
+    /// Only for responses: the body runs until the connection closes.
+    UntilClose,
+}
+
+fn framing(headers: &Headers, is_request: bool) -> io::Result<Framing> {
+    if let Some(encoding) = headers.get("Transfer-Encoding") {
+        return match encoding.rsplit(',').next().map(str::trim) {
+            Some(last) if last.eq_ignore_ascii_case("chunked") => Ok(Framing::Chunked),
+            _ => Err(invalid("unsupported transfer-encoding")),
+        };
+    }
+
+    match headers.get("Content-Length") {
+        Some(length) => length
+            .parse()
+            .map(Framing::Length)
+            .map_err(|_| invalid("malformed content-length")),
+        None if is_request => Ok(Framing::Length(0)),
+        None => Ok(Framing::UntilClose),
+    }
+}
+
+/// A TCP connection with a read buffer, shared by server and client.
+struct Connection {
+    stream: TcpStream,
+    buf: Vec<u8>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Self {
+        Connection {
+            stream,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Reads more bytes into the buffer, returning 0 at end of stream.
+    async fn fill(&mut self) -> io::Result<usize> {
+        let mut chunk = [0; 4096];
+        let n = self.stream.read(&mut chunk).await?;
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(n)
+    }
+
+    /// Reads up to and including the next `\r\n`, returning the line
+    /// without it.
+    async fn read_line(&mut self, limit: usize) -> io::Result<Vec<u8>> {
+        loop {
+            if let Some(end) = find(&self.buf, b"\r\n") {
+                let line = self.buf[..end].to_vec();
+                self.buf.drain(..end + 2);
+                return Ok(line);
+            }
+            if self.buf.len() > limit {
+                return Err(invalid("line too long"));
+            }
+            if self.fill().await? == 0 {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+
+    /// Reads a message head, or `None` if the peer closed the connection
+    /// cleanly before sending one.
+    async fn read_head(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(end) = find(&self.buf, b"\r\n\r\n") {
+                let head = self.buf[..end].to_vec();
+                self.buf.drain(..end + 4);
+                return Ok(Some(head));
+            }
+            if self.buf.len() > MAX_HEAD {
+                return Err(io::Error::new(ErrorKind::InvalidData, HeadTooLarge));
+            }
+            if self.fill().await? == 0 {
+                return match self.buf.is_empty() {
+                    true => Ok(None),
+                    false => Err(ErrorKind::UnexpectedEof.into()),
+                };
+            }
+        }
+    }
+
+    async fn read_exact(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        while self.buf.len() < len {
+            if self.fill().await? == 0 {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+        }
+        Ok(self.buf.drain(..len).collect())
+    }
+
+    async fn read_body(&mut self, framing: Framing) -> io::Result<Vec<u8>> {
+        match framing {
+            Framing::Length(len) if len > MAX_BODY => {
+                Err(io::Error::new(ErrorKind::InvalidData, BodyTooLarge))
+            }
+            Framing::Length(len) => self.read_exact(len).await,
+            Framing::Chunked => self.read_chunked().await,
+            Framing::UntilClose => {
+                while self.fill().await? != 0 {
+                    if self.buf.len() > MAX_BODY {
+                        return Err(io::Error::new(ErrorKind::InvalidData, BodyTooLarge));
+                    }
+                }
+                Ok(std::mem::take(&mut self.buf))
+            }
+        }
+    }
+
+    async fn read_chunked(&mut self) -> io::Result<Vec<u8>> {
+        let mut body = Vec::new();
+
+        loop {
+            let line = self.read_line(MAX_HEAD).await?;
+            let size = parse_chunk_size(&line)?;
+
+            if size == 0 {
+                // skip trailers up to the final blank line
+                while !self.read_line(MAX_HEAD).await?.is_empty() {}
+                return Ok(body);
+            }
+            // `body` never exceeds `MAX_BODY`; a sum could overflow
+            if size > MAX_BODY - body.len() {
+                return Err(io::Error::new(ErrorKind::InvalidData, BodyTooLarge));
+            }
+
+            body.extend(self.read_exact(size).await?);
+            if self.read_exact(2).await? != b"\r\n" {
+                return Err(invalid("chunk is longer than its size"));
+            }
+        }
+    }
+}
+
+/// Parses a chunk-size line, ignoring any chunk extensions.
+fn parse_chunk_size(line: &[u8]) -> io::Result<usize> {
+    let line = std::str::from_utf8(line).map_err(|_| invalid("chunk size is not utf-8"))?;
+    let size = line.split(';').next().unwrap_or_default().trim();
+
+    usize::from_str_radix(size, 16).map_err(|_| invalid("malformed chunk size"))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[derive(Debug)]
+struct HeadTooLarge;
+
+impl fmt::Display for HeadTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("message head too large")
+    }
+}
+
+impl std::error::Error for HeadTooLarge {}
+
+#[derive(Debug)]
+struct BodyTooLarge;
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("message body too large")
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
+
+/// The status to answer a malformed request with.
+fn error_status(error: &io::Error) -> u16 {
+    match error.get_ref() {
+        Some(inner) if inner.is::<HeadTooLarge>() => 431,
+        Some(inner) if inner.is::<BodyTooLarge>() => 413,
+        _ => 400,
+    }
+}
+
+// Begin Implementing the Writer
+
+fn encode_request(request: &Request) -> Vec<u8> {
+    let mut out = format!(
+        "{} {} {}\r\n",
+        request.method, request.path, request.version
+    );
+    for (name, value) in request.headers.iter() {
+        if !name.eq_ignore_ascii_case("Content-Length") {
+            out.push_str(&format!("{name}: {value}\r\n"));
+        }
+    }
+    out.push_str(&format!("Content-Length: {}\r\n\r\n", request.body.len()));
+
+    let mut out = out.into_bytes();
+    out.extend_from_slice(&request.body);
+    out
+}
+
+fn encode_response(response: &Response, version: Version, keep_alive: bool) -> Vec<u8> {
+    let mut out = format!(
+        "{version} {} {}\r\n",
+        response.status,
+        reason(response.status)
+    );
+    for (name, value) in response.headers.iter() {
+        let framing = ["Content-Length", "Transfer-Encoding", "Connection"];
+        if !framing
+            .iter()
+            .any(|header| name.eq_ignore_ascii_case(header))
+        {
+            out.push_str(&format!("{name}: {value}\r\n"));
+        }
+    }
+    if !keep_alive {
+        out.push_str("Connection: close\r\n");
+    } else if version == Version::Http10 {
+        out.push_str("Connection: keep-alive\r\n");
+    }
+
+    match &response.body {
+        // HTTP/1.0 peers don't understand chunks, send those in one piece
+        Body::Chunked(chunks) if version == Version::Http11 => {
+            out.push_str("Transfer-Encoding: chunked\r\n\r\n");
+            let mut out = out.into_bytes();
+            for chunk in chunks.iter().filter(|chunk| !chunk.is_empty()) {
+                out.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+                out.extend_from_slice(chunk);
+                out.extend_from_slice(b"\r\n");
+            }
+            out.extend_from_slice(b"0\r\n\r\n");
+            out
+        }
+        body => {
+            let body = body.bytes();
+            out.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+            let mut out = out.into_bytes();
+            out.extend_from_slice(&body);
+            out
+        }
+    }
+}
+
+// Begin Implementing the Router
+
+type BoxFuture = Pin<Box<dyn Future<Output = Response> + Send + 'static>>;
+type Handler = Arc<dyn Fn(Request) -> BoxFuture + Send + Sync + 'static>;
+
+/// Maps `(method, path)` pairs to async handlers.
+#[derive(Clone, Default)]
+pub struct Router {
+    routes: HashMap<String, Vec<(String, Handler)>>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn route<F, Fut>(mut self, method: &str, path: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Response> + Send + 'static,
+    {
+        let handler: Handler = Arc::new(move |request| Box::pin(handler(request)));
+        self.routes
+            .entry(path.to_owned())
+            .or_default()
+            .push((method.to_ascii_uppercase(), handler));
+        self
+    }
+
+    pub fn get<F, Fut>(self, path: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Response> + Send + 'static,
+    {
+        self.route("GET", path, handler)
+    }
+
+    pub fn post<F, Fut>(self, path: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Response> + Send + 'static,
+    {
+        self.route("POST", path, handler)
+    }
+
+    /// Runs the handler for `request`, answering 404 for unknown paths and
+    /// 405 for known paths with another method.
+    pub async fn dispatch(&self, request: Request) -> Response {
+        let Some(handlers) = self
+            .routes
+            .get(request.path.split('?').next().unwrap_or(""))
+        else {
+            return Response::text(404, "not found\n");
+        };
+
+        // HEAD is answered by the GET handler, without the body
+        let method = match request.method.as_str() {
+            "HEAD" => "GET",
+            method => method,
+        };
+
+        match handlers.iter().find(|(allowed, _)| allowed == method) {
+            Some((_, handler)) => handler(request).await,
+            None => {
+                let allow: Vec<&str> = handlers.iter().map(|(method, _)| method.as_str()).collect();
+                Response::text(405, "method not allowed\n").with_header("Allow", allow.join(", "))
+            }
+        }
+    }
+}
+
+// Begin Implementing the Server
+
+pub struct Server {
+    listener: TcpListener,
+    router: Arc<Router>,
+}
+
+impl Server {
+    pub fn bind(addr: impl std::net::ToSocketAddrs, router: Router) -> io::Result<Self> {
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            router: Arc::new(router),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts connections forever, serving each one on its own task.
+    pub async fn serve(self, spawner: Spawner) -> io::Result<()> {
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            let router = self.router.clone();
+
+            spawner.spawn(async move {
+                let _ = serve_connection(stream, &router).await;
+            });
+        }
+    }
+}
+
+/// Serves requests on one connection until either side closes it.
+pub async fn serve_connection(stream: TcpStream, router: &Router) -> io::Result<()> {
+    let mut conn = Connection::new(stream);
+
+    loop {
+        let request = match read_request(&mut conn).await {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(error) if error.kind() == ErrorKind::InvalidData => {
+                let response = Response::text(error_status(&error), format!("{error}\n"));
+                let bytes = encode_response(&response, Version::Http11, false);
+                return conn.stream.write_all(&bytes).await;
+            }
+            Err(error) => return Err(error),
+        };
+
+        let keep_alive = request.keep_alive();
+        let version = request.version;
+        let is_head = request.method == "HEAD";
+
+        let response = router.dispatch(request).await;
+        let mut bytes = encode_response(&response, version, keep_alive);
+        if is_head {
+            // keep the headers (including the length) but drop the body
+            let head_len = find(&bytes, b"\r\n\r\n").map_or(bytes.len(), |end| end + 4);
+            bytes.truncate(head_len);
+        }
+        conn.stream.write_all(&bytes).await?;
+
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+async fn read_request(conn: &mut Connection) -> io::Result<Option<Request>> {
+    let Some(head) = conn.read_head().await? else {
+        return Ok(None);
+    };
+
+    let mut request = parse_request_head(&head)?;
+    let framing = framing(&request.headers, true)?;
+    request.body = conn.read_body(framing).await?;
+
+    Ok(Some(request))
+}
+
+// Begin Implementing the Client
+
+/// A keep-alive connection to one HTTP server.
+pub struct Client {
+    conn: Option<Connection>,
+    addr: SocketAddr,
+}
+
+impl Client {
+    pub async fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+
+        Ok(Client {
+            conn: Some(Connection::new(stream)),
+            addr,
+        })
+    }
+
+    /// Sends `request` and reads the response, on the kept-alive
+    /// connection if there is one.
+    ///
+    /// The server may have closed that connection while it was idle. If it
+    /// turns out closed before any of the response arrived, an idempotent
+    /// request is sent again once on a new connection; any other request
+    /// fails, as the server may already have acted on it.
+    pub async fn send(&mut self, mut request: Request) -> io::Result<Response> {
+        if request.headers.get("Host").is_none() {
+            request.headers.insert("Host", self.addr.to_string());
+        }
+        let bytes = encode_request(&request);
+
+        if let Some(conn) = self.conn.take() {
+            match self.exchange(conn, &request, &bytes).await? {
+                Some(response) => return Ok(response),
+                None if is_idempotent(&request.method) => {}
+                None => return Err(ErrorKind::UnexpectedEof.into()),
+            }
+        }
+
+        let conn = Connection::new(TcpStream::connect(self.addr).await?);
+        self.exchange(conn, &request, &bytes)
+            .await?
+            .ok_or_else(|| ErrorKind::UnexpectedEof.into())
+    }
+
+    /// One request on `conn`, or `None` if the connection was closed
+    /// before any of the response came back.
+    async fn exchange(
+        &mut self,
+        mut conn: Connection,
+        request: &Request,
+        bytes: &[u8],
+    ) -> io::Result<Option<Response>> {
+        let closed = |error: &io::Error| {
+            matches!(
+                error.kind(),
+                ErrorKind::BrokenPipe | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted
+            )
+        };
+
+        match conn.stream.write_all(bytes).await {
+            Err(error) if closed(&error) => return Ok(None),
+            written => written?,
+        }
+        let head = match conn.read_head().await {
+            Ok(Some(head)) => head,
+            Ok(None) => return Ok(None),
+            Err(error) if closed(&error) && conn.buf.is_empty() => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let (version, mut response) = parse_response_head(&head)?;
+
+        let framing = match (request.method.as_str(), response.status) {
+            ("HEAD", _) | (_, 204) | (_, 304) => Framing::Length(0),
+            _ => framing(&response.headers, false)?,
+        };
+        let reusable = framing != Framing::UntilClose
+            && request.keep_alive()
+            && keep_alive(version, &response.headers);
+
+        response.body = Body::Full(conn.read_body(framing).await?);
+
+        if reusable {
+            self.conn = Some(conn);
+        }
+        Ok(Some(response))
+    }
+
+    pub async fn get(&mut self, path: &str) -> io::Result<Response> {
+        self.send(Request::get(path)).await
+    }
+}
+
+/// Methods that may be sent twice with the effect of once.
+fn is_idempotent(method: &str) -> bool {
+    matches!(
+        method,
+        "GET" | "HEAD" | "PUT" | "DELETE" | "OPTIONS" | "TRACE"
+    )
+}
+
+/// Sends a single `GET` on a fresh connection.
+pub async fn get(addr: SocketAddr, path: &str) -> io::Result<Response> {
+    Client::connect(addr)
+        .await?
+        .send(Request::get(path).with_header("Connection", "close"))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::executor::{sleep, Builder, Spawner, TcpListener};
+
+    #[test]
+    fn parses_a_request_head() {
+        let head = b"POST /status?verbose=1 HTTP/1.1\r\nHost: gateway\r\nContent-Length:  4 ";
+        let request = parse_request_head(head).unwrap();
+
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/status?verbose=1");
+        assert_eq!(request.version, Version::Http11);
+        assert_eq!(request.headers.get("host"), Some("gateway"));
+        assert_eq!(request.headers.get("CONTENT-LENGTH"), Some("4"));
+    }
+
+    #[test]
+    fn rejects_malformed_request_heads() {
+        for head in [
+            &b"GET /"[..],
+            b"GET / HTTP/2.0",
+            b"GET status HTTP/1.1",
+            b"GET / HTTP/1.1\r\nNo colon here",
+            b"GET / HTTP/1.1\r\nBad : spacing",
+        ] {
+            assert!(parse_request_head(head).is_err(), "{:?}", head);
+        }
+    }
+
+    #[test]
+    fn parses_a_response_head() {
+        let (version, response) =
+            parse_response_head(b"HTTP/1.0 404 Not Found\r\nConnection: keep-alive").unwrap();
+
+        assert_eq!(version, Version::Http10);
+        assert_eq!(response.status, 404);
+        assert!(keep_alive(version, &response.headers));
+    }
+
+    #[test]
+    fn parses_chunk_sizes_with_extensions() {
+        assert_eq!(parse_chunk_size(b"1a").unwrap(), 26);
+        assert_eq!(parse_chunk_size(b"FF;name=value").unwrap(), 255);
+        assert!(parse_chunk_size(b"zz").is_err());
+    }
+
+    #[test]
+    fn encodes_chunked_responses() {
+        let response = Response::new(200).with_chunks(vec![b"hello ".to_vec(), b"world".to_vec()]);
+        let bytes = encode_response(&response, Version::Http11, true);
+
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nhello \r\n5\r\nworld\r\n0\r\n\r\n"
+        );
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .get("/status", |_| async { Response::text(200, "up\n") })
+            .post("/echo", |request: Request| async move {
+                Response::new(200).with_body(request.body)
+            })
+            .get("/chunks", |_| async {
+                Response::new(200).with_chunks(vec![b"a".to_vec(), b"bc".to_vec()])
+            })
+    }
+
+    /// Serves `router()` on a free port until the executor stops.
+    fn spawn_server(spawner: &Spawner) -> SocketAddr {
+        let server = Server::bind("127.0.0.1:0", router()).unwrap();
+        let addr = server.local_addr().unwrap();
+        spawner.spawn({
+            let spawner = spawner.clone();
+            async move {
+                let _ = server.serve(spawner).await;
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn server_routes_requests_over_one_keep_alive_connection() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let responses = executor.block_on(async move {
+            let addr = spawn_server(&spawner);
+
+            let mut client = Client::connect(addr).await.unwrap();
+            let mut responses = Vec::new();
+            for request in [
+                Request::get("/status"),
+                Request::post("/echo", "ping"),
+                Request::get("/chunks"),
+                Request::get("/missing"),
+                Request::new("DELETE", "/status"),
+            ] {
+                responses.push(client.send(request).await.unwrap());
+            }
+
+            // all of the above reused the first connection
+            assert!(client.conn.is_some());
+            responses
+        });
+
+        let summary: Vec<(u16, Vec<u8>)> = responses
+            .iter()
+            .map(|response| (response.status, response.body.bytes()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (200, b"up\n".to_vec()),
+                (200, b"ping".to_vec()),
+                (200, b"abc".to_vec()),
+                (404, b"not found\n".to_vec()),
+                (405, b"method not allowed\n".to_vec()),
+            ]
+        );
+        assert_eq!(responses[4].headers.get("Allow"), Some("GET"));
+    }
+
+    #[test]
+    fn client_resends_idempotent_requests_on_a_connection_closed_while_idle() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let (second, post, connections) = executor.block_on(async move {
+            // answers one request per connection, then closes it unannounced
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let connections = Arc::new(AtomicUsize::new(0));
+            let accepted = connections.clone();
+            spawner.spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    accepted.fetch_add(1, Ordering::SeqCst);
+                    let mut conn = Connection::new(stream);
+                    let _ = conn.read_head().await;
+                    let _ = conn
+                        .stream
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                        .await;
+                }
+            });
+
+            let mut client = Client::connect(addr).await.unwrap();
+            assert_eq!(client.get("/").await.unwrap().body.bytes(), b"ok");
+            sleep(Duration::from_millis(10)).await;
+            let second = client.get("/").await.unwrap().body.bytes();
+
+            sleep(Duration::from_millis(10)).await;
+            let post = client.send(Request::post("/", "once")).await;
+            (second, post, connections.load(Ordering::SeqCst))
+        });
+
+        assert_eq!(second, b"ok");
+        assert_eq!(post.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(connections, 2);
+    }
+
+    #[test]
+    fn server_decodes_chunked_request_bodies_and_honours_connection_close() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let raw = executor.block_on(async move {
+            let addr = spawn_server(&spawner);
+
+            let stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(
+                    b"POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n\
+                      4\r\nping\r\n5;ext=1\r\n-pong\r\n0\r\nTrailer: x\r\n\r\n",
+                )
+                .await
+                .unwrap();
+
+            // the server closes after answering, so read to the end
+            let mut raw = Vec::new();
+            let mut buf = [0; 256];
+            loop {
+                match stream.read(&mut buf).await.unwrap() {
+                    0 => break raw,
+                    n => raw.extend_from_slice(&buf[..n]),
+                }
+            }
+        });
+
+        let raw = String::from_utf8(raw).unwrap();
+        assert!(raw.starts_with("HTTP/1.1 200 OK\r\n"), "{raw}");
+        assert!(raw.contains("Connection: close\r\n"), "{raw}");
+        assert!(raw.ends_with("\r\n\r\nping-pong"), "{raw}");
+    }
+
+    #[test]
+    fn server_reads_chunked_bodies_split_at_every_byte() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let response = executor.block_on(async move {
+            let addr = spawn_server(&spawner);
+
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let request = b"POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                            4\r\nping\r\n5\r\n-pong\r\n0\r\n\r\n";
+            // each byte arrives on its own, CRLFs included
+            for byte in request {
+                stream.write_all(&[*byte]).await.unwrap();
+                sleep(Duration::from_millis(1)).await;
+            }
+
+            let mut conn = Connection::new(stream);
+            let head = conn.read_head().await.unwrap().unwrap();
+            let (_, response) = parse_response_head(&head).unwrap();
+            let framing = framing(&response.headers, false).unwrap();
+            (response.status, conn.read_body(framing).await.unwrap())
+        });
+
+        assert_eq!(response, (200, b"ping-pong".to_vec()));
+    }
+
+    #[test]
+    fn server_refuses_a_chunk_size_that_would_overflow() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let response = executor.block_on(async move {
+            let addr = spawn_server(&spawner);
+
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let request = format!(
+                "POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                 4\r\nping\r\n{:x}\r\n",
+                usize::MAX
+            );
+            stream.write_all(request.as_bytes()).await.unwrap();
+
+            let mut conn = Connection::new(stream);
+            let head = conn.read_head().await.unwrap().unwrap();
+            parse_response_head(&head).unwrap().1
+        });
+
+        assert_eq!(response.status, 413);
+    }
+
+    #[test]
+    fn server_answers_garbage_with_bad_request() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let response = executor.block_on(async move {
+            let addr = spawn_server(&spawner);
+
+            let stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(b"HELLO\r\n\r\n").await.unwrap();
+
+            let mut conn = Connection::new(stream);
+            let head = conn.read_head().await.unwrap().unwrap();
+            parse_response_head(&head).unwrap().1
+        });
+
+        assert_eq!(response.status, 400);
+    }
+}
@@ -0,0 +1,6 @@
+pub mod codec;
+pub mod echo;
+pub mod executor;
+pub mod http;
+pub mod loadgen;
+pub mod reliable;
@@ -0,0 +1,444 @@
+//! A UDP load generator for the echo server, run by the `loadgen` binary.
+//!
+//! Each worker owns a socket and keeps one message in flight: it sends,
+//! waits for the reply (or a timeout), then sends the next. With a rate
+//! set, sends are scheduled on a fixed grid and latency is measured from
+//! the scheduled time, so a slow reply also counts against the messages
+//! it delayed instead of hiding them.
+
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    echo::Mode,
+    executor::{
+        sleep_until,
+        sync::oneshot,
+        time::{timeout_at, Elapsed},
+        Spawner, UdpSocket,
+    },
+};
+
+/// Every payload starts with its sequence number.
+pub const MIN_MESSAGE_SIZE: usize = 8;
+
+/// How many timed-out messages a worker remembers, to tell their late
+/// replies apart from corrupted ones.
+const LATE_WINDOW: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct LoadConfig {
+    pub target: SocketAddr,
+    /// Concurrent workers, each with its own socket.
+    pub workers: usize,
+    /// Payload size in bytes, at least `MIN_MESSAGE_SIZE`.
+    pub size: usize,
+    /// Total messages per second across all workers, `None` for as fast
+    /// as the replies come back.
+    pub rate: Option<f64>,
+    /// Total messages to send.
+    pub messages: u64,
+    /// Stop early once this much time has passed.
+    pub duration: Option<Duration>,
+    /// How long to wait for each reply before counting it lost.
+    pub timeout: Duration,
+    /// What the server is expected to do to each payload.
+    pub expect: Mode,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        LoadConfig {
+            target: ([127, 0, 0, 1], 8000).into(),
+            workers: 4,
+            size: 64,
+            rate: None,
+            messages: 10_000,
+            duration: None,
+            timeout: Duration::from_millis(500),
+            expect: Mode::Reverse,
+        }
+    }
+}
+
+/// `period * n / d`, or `None` past what a `Duration` holds.
+fn scale(period: Duration, n: u64, d: u64) -> Option<Duration> {
+    let nanos = period.as_nanos().checked_mul(n.into())? / u128::from(d.max(1));
+    let secs = u64::try_from(nanos / 1_000_000_000).ok()?;
+    Some(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+}
+
+/// The deterministic payload for message `seq`.
+fn payload(seq: u64, size: usize) -> Vec<u8> {
+    let mut payload = seq.to_be_bytes().to_vec();
+    payload.extend((MIN_MESSAGE_SIZE..size).map(|i| (seq as usize + i) as u8));
+    payload
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Report {
+    pub sent: u64,
+    pub received: u64,
+    /// No reply within the timeout.
+    pub lost: u64,
+    /// Replies that arrived after their message was counted lost.
+    pub late: u64,
+    /// Replies that matched no message sent.
+    pub mismatched: u64,
+    pub elapsed: Duration,
+    /// Round-trip times of the received messages, sorted.
+    pub latencies: Vec<Duration>,
+    pub size: usize,
+}
+
+impl Report {
+    fn merge(&mut self, other: Report) {
+        self.sent += other.sent;
+        self.received += other.received;
+        self.lost += other.lost;
+        self.late += other.late;
+        self.mismatched += other.mismatched;
+        self.latencies.extend(other.latencies);
+    }
+
+    /// The latency below which `percent` of the replies arrived.
+    pub fn percentile(&self, percent: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let rank = (percent / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        Some(self.latencies[rank.clamp(1, self.latencies.len()) - 1])
+    }
+
+    /// Replies per second.
+    pub fn throughput(&self) -> f64 {
+        self.received as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "sent {}, received {}, lost {}, late {}, mismatched {}",
+            self.sent, self.received, self.lost, self.late, self.mismatched
+        )?;
+
+        let throughput = self.throughput();
+        let mib = throughput * self.size as f64 / (1024.0 * 1024.0);
+        writeln!(
+            f,
+            "throughput {throughput:.1} msg/s, {mib:.2} MiB/s over {:.2?}",
+            self.elapsed
+        )?;
+
+        match self.percentile(0.0) {
+            None => writeln!(f, "latency n/a"),
+            Some(min) => writeln!(
+                f,
+                "latency min {min:.1?}, p50 {:.1?}, p90 {:.1?}, p99 {:.1?}, p99.9 {:.1?}, max {:.1?}",
+                self.percentile(50.0).unwrap(),
+                self.percentile(90.0).unwrap(),
+                self.percentile(99.0).unwrap(),
+                self.percentile(99.9).unwrap(),
+                self.percentile(100.0).unwrap(),
+            ),
+        }
+    }
+}
+
+/// Runs the load described by `config`, with workers on `spawner`.
+pub async fn run(config: LoadConfig, spawner: &Spawner) -> io::Result<Report> {
+    let workers = config.workers.max(1);
+    let config = LoadConfig {
+        workers,
+        size: config.size.max(MIN_MESSAGE_SIZE),
+        ..config
+    };
+
+    let start = Instant::now();
+    let mut results = Vec::new();
+    for worker in 0..workers {
+        // spread the messages as evenly as possible
+        let messages = config.messages / workers as u64
+            + u64::from((worker as u64) < config.messages % workers as u64);
+        let socket = UdpSocket::bind(match config.target {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        })?;
+        let (sender, receiver) = oneshot::channel();
+        let config = config.clone();
+
+        spawner.spawn(async move {
+            let _ = sender.send(run_worker(socket, worker, messages, config, start).await);
+        });
+        results.push(receiver);
+    }
+
+    let mut report = Report {
+        size: config.size,
+        ..Report::default()
+    };
+    for result in results {
+        let worker = result
+            .await
+            .map_err(|_| io::Error::other("load worker panicked"))??;
+        report.merge(worker);
+    }
+    report.elapsed = start.elapsed();
+    report.latencies.sort_unstable();
+
+    Ok(report)
+}
+
+async fn run_worker(
+    socket: UdpSocket,
+    worker: usize,
+    messages: u64,
+    config: LoadConfig,
+    start: Instant,
+) -> io::Result<Report> {
+    let mut report = Report::default();
+    let mut lost = VecDeque::new();
+    let mut buf = vec![0; config.size + 1];
+
+    // workers start staggered across one period, then each sends every
+    // `workers / rate` seconds; a rate too low for a `Duration` never sends
+    // a second message
+    let period = config.rate.map(|rate| {
+        Duration::try_from_secs_f64(config.workers as f64 / rate).unwrap_or(Duration::MAX)
+    });
+    let workers = config.workers as u64;
+    let first = period.map_or(Some(start), |period| {
+        start.checked_add(scale(period, worker as u64, workers)?)
+    });
+    // a stop too far out for an `Instant` is no stop at all
+    let stop = config
+        .duration
+        .and_then(|duration| start.checked_add(duration));
+
+    for n in 0..messages {
+        let scheduled = match (period, first) {
+            (Some(period), Some(first)) => scale(period, n, 1).and_then(|at| first.checked_add(at)),
+            (Some(_), None) => None,
+            (None, _) => Some(Instant::now()),
+        };
+        // past the end of time is past any stop, too
+        let Some(scheduled) = scheduled else {
+            break;
+        };
+        if stop.is_some_and(|stop| scheduled >= stop) {
+            break;
+        }
+        sleep_until(scheduled).await;
+
+        // sequence numbers are unique across workers
+        let seq = n * config.workers as u64 + worker as u64;
+        let message = payload(seq, config.size);
+        let expected = config.expect.reply(message.clone());
+        let sent_at = Instant::now();
+        socket.send_to(&message, config.target).await?;
+        report.sent += 1;
+
+        let deadline = sent_at + config.timeout;
+        let Some(expected) = expected else {
+            // nothing comes back in discard mode, just keep the pace
+            continue;
+        };
+
+        loop {
+            let (amt, from) = match timeout_at(deadline, socket.recv_from(&mut buf)).await {
+                Ok(received) => received?,
+                Err(Elapsed) => {
+                    report.lost += 1;
+                    if lost.len() == LATE_WINDOW {
+                        lost.pop_front();
+                    }
+                    lost.push_back(seq);
+                    break;
+                }
+            };
+            if from != config.target {
+                continue;
+            }
+
+            let reply = &buf[..amt];
+            if reply == expected {
+                report.received += 1;
+                report.latencies.push(scheduled.min(sent_at).elapsed());
+                break;
+            }
+
+            let late = lost.iter().position(|&seq| {
+                config.expect.reply(payload(seq, config.size)).as_deref() == Some(reply)
+            });
+            match late {
+                Some(index) => {
+                    lost.remove(index);
+                    report.late += 1;
+                }
+                None => report.mismatched += 1,
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        echo::{Config, Listener, Stats, Transport},
+        executor::{drain::Drain, rate_limit::RateLimit, Builder},
+    };
+
+    /// Starts an in-process echo server and returns its address.
+    fn echo_server(spawner: &Spawner, mode: Mode) -> SocketAddr {
+        let config = Config {
+            addr: ([127, 0, 0, 1], 0).into(),
+            transport: Transport::Udp,
+            mode,
+            buffer_size: 2048,
+            rate_limit: RateLimit::Unlimited,
+            max_sources: 16,
+            verbosity: 0,
+        };
+        let listener = Listener::bind(&config).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let serving = listener.serve(
+            config,
+            Arc::new(Stats::default()),
+            spawner.clone(),
+            Drain::new(),
+        );
+        spawner.spawn(async move {
+            let _ = serving.await;
+        });
+        addr
+    }
+
+    #[test]
+    fn payloads_carry_their_sequence_number() {
+        let message = payload(0x0102, 12);
+
+        assert_eq!(message.len(), 12);
+        assert_eq!(message[..8], 0x0102u64.to_be_bytes());
+        assert_ne!(payload(1, 12), payload(2, 12));
+    }
+
+    #[test]
+    fn send_times_scale_without_overflowing() {
+        let period = Duration::from_millis(3);
+
+        assert_eq!(scale(period, 1, 2), Some(Duration::from_micros(1500)));
+        // past `u32::MAX` messages, where `period * n as u32` wrapped
+        let n = u64::from(u32::MAX) + 2;
+        assert_eq!(scale(period, n, 1), Some(period * 2 + period * u32::MAX));
+        assert_eq!(scale(Duration::MAX, 2, 1), None);
+        assert_eq!(scale(Duration::MAX, 1, 3), Some(Duration::MAX / 3));
+    }
+
+    #[test]
+    fn a_rate_too_low_to_schedule_sends_once() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let report = executor.block_on(async move {
+            let target = echo_server(&spawner, Mode::Reverse);
+            let config = LoadConfig {
+                target,
+                workers: 1,
+                rate: Some(1e-300),
+                messages: 3,
+                duration: Some(Duration::MAX),
+                ..LoadConfig::default()
+            };
+            run(config, &spawner).await.unwrap()
+        });
+
+        assert_eq!((report.sent, report.received), (1, 1));
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let report = Report {
+            latencies: (1..=100).map(Duration::from_millis).collect(),
+            ..Report::default()
+        };
+
+        assert_eq!(report.percentile(0.0), Some(Duration::from_millis(1)));
+        assert_eq!(report.percentile(50.0), Some(Duration::from_millis(50)));
+        assert_eq!(report.percentile(99.0), Some(Duration::from_millis(99)));
+        assert_eq!(report.percentile(100.0), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn every_reversed_reply_is_verified() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let report = executor.block_on(async move {
+            let target = echo_server(&spawner, Mode::Reverse);
+            let config = LoadConfig {
+                target,
+                workers: 3,
+                size: 100,
+                messages: 300,
+                ..LoadConfig::default()
+            };
+            run(config, &spawner).await.unwrap()
+        });
+
+        assert_eq!((report.sent, report.received), (300, 300));
+        assert_eq!(report.lost + report.mismatched, 0);
+        assert_eq!(report.latencies.len(), 300);
+    }
+
+    #[test]
+    fn replies_from_the_wrong_mode_are_mismatched() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let report = executor.block_on(async move {
+            let target = echo_server(&spawner, Mode::Uppercase);
+            let config = LoadConfig {
+                target,
+                workers: 1,
+                messages: 5,
+                timeout: Duration::from_millis(50),
+                ..LoadConfig::default()
+            };
+            run(config, &spawner).await.unwrap()
+        });
+
+        assert_eq!(report.received, 0);
+        assert_eq!(report.mismatched, 5);
+        assert_eq!(report.lost, 5);
+    }
+
+    #[test]
+    fn a_rate_paces_the_sends() {
+        let (executor, spawner) = Builder::new().reactor_thread().build().unwrap();
+
+        let report = executor.block_on(async move {
+            let target = echo_server(&spawner, Mode::Reverse);
+            let config = LoadConfig {
+                target,
+                workers: 2,
+                rate: Some(200.0),
+                messages: 20,
+                ..LoadConfig::default()
+            };
+            run(config, &spawner).await.unwrap()
+        });
+
+        assert_eq!(report.received, 20);
+        // the last message is scheduled 19 / 200 s = 95 ms after the first
+        assert!(report.elapsed >= Duration::from_millis(90), "{report}");
+    }
+}
@@ -1,291 +1,257 @@
-use crate::executor::new_executor_spawner;
-
-mod executor {
-    use std::{
-        collections::{hash_map::Entry, HashMap}, future::Future, io::{self, ErrorKind}, net::{SocketAddr, ToSocketAddrs}, pin::Pin, sync::{mpsc, Arc, Mutex, OnceLock}, task::{Context, Poll, RawWaker, RawWakerVTable, Waker}
-    };
-
-    use mio::{Interest, Registry, Token};
-
-    // Begin Implementing The Executor
-    pub(crate) struct Task {
-        future: Mutex<Pin<Box<dyn Future<Output = ()> + Send + 'static>>>,
-        spawner: Spawner,
-    }
-
-    pub struct Executor {
-        ready_queue: std::sync::mpsc::Receiver<Arc<Task>>,
-    }
-
-    impl Executor {
-        pub fn run(&self) {
-            while let Ok(task) = self.ready_queue.recv() {
-                let mut future = task.future.lock().unwrap();
-
-                // make a context (explained later)
-                let waker = Arc::clone(&task).waker();
-                let mut context = Context::from_waker(&waker);
-
-                // allow the future some CPU time to make progress
-                let _ = future.as_mut().poll(&mut context);
-            }
-        }
-    }
-
-    // Begin Implementing a Spawner
-    #[derive(Clone)]
-    pub struct Spawner {
-        task_sender: std::sync::mpsc::SyncSender<Arc<Task>>,
-    }
-
-    pub fn new_executor_spawner() -> (Executor, Spawner) {
-        const MAX_QUEUED_TASKS: usize = 10_000;
-
-        let (task_sender, ready_queue) = mpsc::sync_channel(MAX_QUEUED_TASKS);
-
-        (Executor { ready_queue }, Spawner { task_sender })
-    }
-
-    impl Spawner {
-        pub fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
-            let task = Arc::new(Task {
-                future: Mutex::new(Box::pin(future)),
-                spawner: self.clone(),
-            });
-            self.spawn_task(task)
-        }
-
-        pub(crate) fn spawn_task(&self, task: Arc<Task>) {
-            self.task_sender.send(task).unwrap();
-        }
-    }
-
-    // Begin Constructing a Waker
-    fn clone(ptr: *const ()) -> RawWaker {
-        let ori: Arc<Task> = unsafe { Arc::from_raw(ptr as _) };
-
-        // Increment the inner counter of the arc.
-        let cloned = ori.clone();
-
-        std::mem::forget(ori);
-        std::mem::forget(cloned);
-
-        RawWaker::new(ptr, &Task::WAKER_VTABLE)
-    }
-
-    fn drop(ptr: *const ()) {
-        let _: Arc<Task> = unsafe { Arc::from_raw(ptr as _) };
-    }
-
-    fn wake(ptr: *const ()) {
-        let arc: Arc<Task> = unsafe { Arc::from_raw(ptr as _) };
-        let spawner = arc.spawner.clone();
-
-        spawner.spawn_task(arc);
-    }
-
-    fn wake_by_ref(ptr: *const ()) {
-        let arc: Arc<Task> = unsafe { Arc::from_raw(ptr as _) };
-
-        arc.spawner.spawn_task(arc.clone());
-
-        // we don't actually have ownership of this arc value
-        // therefore we must not drop `arc`
-        std::mem::forget(arc)
-    }
-
-    impl Task {
-        const WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop);
-
-        /*
-        Why is all this unsafe pointer business required here? It looks like the code could use a Wake trait instead
-        Because how the Wake trait cannot be turned into an object, due to the fact that .clone() returns Self. It also gives the following hint:
-        note: for a trait to be "object safe" it needs to allow building a vtable to allow the call to be resolvable dynamically
-        And that concludes the reason why wakers require a manual vtable. The requirement of erased types combined with a Clone bound make it impossible to use a more standard trait-based approach
-        */
-        pub fn waker(self: Arc<Self>) -> Waker {
-            let opaque_ptr = Arc::into_raw(self) as *const ();
-            let vtable = &Self::WAKER_VTABLE;
+use std::{
+    fmt,
+    io::{self, Write},
+    net::{IpAddr, SocketAddr},
+    process::ExitCode,
+    str::FromStr,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use async_runtime_with_mio::{
+    codec::MAX_DATAGRAM_SIZE,
+    echo::{self, Config, Listener, Mode, Stats, Transport},
+    executor::{
+        drain::Drain,
+        rate_limit::RateLimit,
+        schedule::{Schedule, Scheduler},
+        signal, Builder, DriverMode, IoBackend, Spawner,
+    },
+};
+use clap::{builder::RangedU64ValueParser, Parser};
+
+/// An echo server on the mio-based runtime.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// Address to listen on.
+    #[arg(short, long, default_value = "127.0.0.1")]
+    bind: IpAddr,
+
+    /// Port to listen on, 0 picks a free one.
+    #[arg(short, long, default_value_t = 8000)]
+    port: u16,
+
+    /// What to send back: echo, reverse, discard or uppercase.
+    #[arg(short, long, default_value_t = Mode::Reverse)]
+    mode: Mode,
+
+    /// udp, tcp, or reliable (UDP with retransmissions).
+    #[arg(short, long, default_value_t = Transport::Udp)]
+    transport: Transport,
+
+    /// Largest datagram accepted, or the TCP read size, in bytes.
+    #[arg(
+        long,
+        default_value_t = MAX_DATAGRAM_SIZE,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..=MAX_DATAGRAM_SIZE as u64),
+    )]
+    buffer_size: usize,
+
+    /// Replies per second allowed per UDP or reliable source, 0 for no
+    /// limit.
+    #[arg(long, default_value_t = 32.0, value_parser = parse_rate)]
+    rate: f64,
+
+    /// Replies a UDP or reliable source may get in a burst.
+    #[arg(long, default_value_t = 64)]
+    burst: u32,
+
+    /// How `--rate` is enforced: token (bursts up front, then the rate) or
+    /// leaky (bursts absorbed while the source stays ahead of the rate).
+    #[arg(long, default_value_t = Limiter::Token)]
+    limiter: Limiter,
+
+    /// Serve counters on `GET /status` at this address.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    status: SocketAddr,
+
+    /// Don't start the status server.
+    #[arg(long)]
+    no_status: bool,
+
+    /// Print the counters to stderr on this schedule, e.g. `@every 10s`
+    /// or `*/1m`.
+    #[arg(long)]
+    report: Option<Schedule>,
+
+    /// Seconds to let open connections finish after SIGINT or SIGTERM
+    /// before closing them.
+    #[arg(long, default_value = "5", value_parser = parse_seconds)]
+    grace: Duration,
+
+    /// Poll the reactor on the executor thread instead of its own.
+    #[arg(long)]
+    current_thread: bool,
+
+    /// Send and receive datagrams through io_uring, where available.
+    #[arg(long)]
+    io_uring: bool,
+
+    /// Log connections and drops; repeat to log every message.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
 
-            unsafe { Waker::from_raw(RawWaker::new(opaque_ptr, vtable)) }
+impl Args {
+    fn config(&self) -> Config {
+        let rate_limit = match (self.rate > 0.0, self.limiter) {
+            (false, _) => RateLimit::Unlimited,
+            (true, Limiter::Token) => RateLimit::TokenBucket {
+                burst: self.burst.max(1),
+                per_second: self.rate,
+            },
+            (true, Limiter::Leaky) => RateLimit::LeakyBucket {
+                capacity: self.burst.max(1),
+                per_second: self.rate,
+            },
+        };
+
+        Config {
+            addr: SocketAddr::new(self.bind, self.port),
+            transport: self.transport,
+            mode: self.mode,
+            buffer_size: self.buffer_size,
+            rate_limit,
+            max_sources: 4096,
+            verbosity: self.verbose,
         }
     }
+}
 
-    // Begin Implementing the Reactor
-    pub enum Status {
-        Awaited(Waker),
-        Happened,
-    }
-
-    pub struct Reactor {
-        registry: Registry,
-        statuses: Mutex<HashMap<Token, Status>>,
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate >= 0.0 => Ok(rate),
+        _ => Err(format!("`{s}` is not a number of replies per second")),
     }
+}
 
-    impl Reactor {
-        pub fn get() -> &'static Self {
-            static REACTOR: OnceLock<Reactor> = OnceLock::new();
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("`{s}` is not a number of seconds"))
+}
 
-            REACTOR.get_or_init(|| {
-                let poll = mio::Poll::new().unwrap();
-                let reactor = Reactor {
-                    registry: poll.registry().try_clone().unwrap(),
-                    statuses: Mutex::new(HashMap::new()),
-                };
+/// The `RateLimit` policy picked by `--limiter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Limiter {
+    Token,
+    Leaky,
+}
 
-                std::thread::Builder::new()
-                    .name("reactor".to_owned())
-                    .spawn(|| run(poll))
-                    .unwrap();
+impl FromStr for Limiter {
+    type Err = String;
 
-                reactor
-            })
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "token" => Ok(Limiter::Token),
+            "leaky" => Ok(Limiter::Leaky),
+            _ => Err(format!("unknown limiter `{s}`, expected token or leaky")),
         }
     }
+}
 
-    fn run(mut poll: mio::Poll) -> ! {
-        let reactor = Reactor::get();
-        let mut events = mio::Events::with_capacity(1024);
-
-        loop {
-            poll.poll(&mut events, None).unwrap();
-
-            for event in &events {
-                let mut guard = reactor.statuses.lock().unwrap();
-
-                let previous = guard.insert(event.token(), Status::Happened);
-
-                if let Some(Status::Awaited(waker)) = previous {
-                    waker.wake();
-                }
-            }
-        }
+impl fmt::Display for Limiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Limiter::Token => "token",
+            Limiter::Leaky => "leaky",
+        })
     }
+}
 
+fn main() -> ExitCode {
+    let args = Args::parse();
 
-    // async udpsocket
-    pub struct UdpSocket {
-        socket: mio::net::UdpSocket,
-        token: Token,
-    }
+    // runtime events, e.g. `RUST_LOG=async_runtime_with_mio::executor=debug`
+    #[cfg(feature = "tracing")]
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(io::stderr)
+        .init();
 
-    impl Reactor {
-        fn unique_token(&self) -> Token {
-            use std::sync::atomic::{AtomicUsize, Ordering};
-            static CURRENT_TOKEN: AtomicUsize = AtomicUsize::new(0);
-            Token(CURRENT_TOKEN.fetch_add(1, Ordering::Relaxed))
+    let mode = match args.current_thread {
+        true => DriverMode::CurrentThread,
+        false => DriverMode::ReactorThread,
+    };
+    let io_backend = match args.io_uring {
+        true => IoBackend::IoUring,
+        false => IoBackend::Epoll,
+    };
+    let (executor, spawner) = match Builder::new().mode(mode).io_backend(io_backend).build() {
+        Ok(runtime) => runtime,
+        Err(error) => {
+            eprintln!("echo server failed to start: {error}");
+            return ExitCode::FAILURE;
         }
+    };
+    if executor.io_backend() != io_backend {
+        eprintln!("io_uring is not available, falling back to epoll");
     }
 
-    impl UdpSocket {
-        pub fn bind(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
-            let std_socket = std::net::UdpSocket::bind(addr)?;
-            std_socket.set_nonblocking(true)?;
-
-            let mut socket = mio::net::UdpSocket::from_std(std_socket);
-
-            let reactor = Reactor::get();
-            let token = reactor.unique_token();
-
-            Reactor::get().registry.register(
-                &mut socket,
-                token,
-                Interest::READABLE | Interest::WRITABLE,
-            )?;
-
-            Ok(self::UdpSocket { socket, token })
+    // sockets register with the reactor of the executor they're created on
+    match executor.block_on(run(args, spawner)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("echo server stopped: {error}");
+            ExitCode::FAILURE
         }
     }
+}
 
-    impl UdpSocket {
-        pub async fn send_to(&self, buf: &[u8], dest: SocketAddr) -> std::io::Result<usize> {
-            loop {
-                match self.socket.send_to(buf, dest) {
-                    Ok(value) => return Ok(value),
-                    Err(error) if error.kind() == ErrorKind::WouldBlock => {
-                        std::future::poll_fn(|cx| {
-                            Reactor::get().poll(self.token, cx)
-                        }).await?
-                    }
-                    Err(error) => return Err(error),
-                }
+async fn run(args: Args, spawner: Spawner) -> io::Result<()> {
+    let config = args.config();
+    let listener = Listener::bind(&config)?;
+    let stats = Arc::new(Stats::default());
+    // before the banner, so that a signal right after it is caught
+    let signals = signal::shutdown()?;
+
+    // scripts and tests read the address from here when using port 0
+    println!(
+        "listening on {}://{} ({})",
+        config.transport,
+        listener.local_addr()?,
+        config.mode
+    );
+    io::stdout().flush()?;
+
+    if !args.no_status {
+        let (addr, stats, status_spawner) = (args.status, stats.clone(), spawner.clone());
+        spawner.spawn_named("status-server", async move {
+            if let Err(error) = echo::status_server(addr, stats, status_spawner).await {
+                eprintln!("status server on {addr} stopped: {error}");
             }
-        }
+        });
     }
 
-    impl Reactor {
-        pub fn poll(&self, token: Token, cx: &mut Context) -> Poll<io::Result<()>> {
-            let mut guard = self.statuses.lock().unwrap();
-            match guard.entry(token) {
-                // If there was no status inserted previously, we simply store the waker, 
-                // so that the run function will respawn the future when the event happens.
-                Entry::Vacant(vacant) => {
-                    vacant.insert(Status::Awaited(cx.waker().clone()));
-                    Poll::Pending
-                }
-                // 
-                Entry::Occupied(mut occupied) => {
-                    match occupied.get() {
-                        Status::Awaited(waker) => {
-                            // skip clone is wakers are the same
-                            // If there was already a waker there, we update it 
-                            // if it’s different from the waker in our current context
-                            if !waker.will_wake(cx.waker()) {
-                                occupied.insert(Status::Awaited(cx.waker().clone()));
-                            }
-                            Poll::Pending
-                        }
-                        Status::Happened => {
-                            occupied.remove();
-                            Poll::Ready(Ok(()))
-                        }
-                    }
-                }
-            }
-        }
+    let mut scheduler = Scheduler::new(spawner.clone());
+    if let Some(schedule) = args.report {
+        let stats = stats.clone();
+        scheduler.add("stats-flush", schedule, move || {
+            eprintln!(
+                "received {} echoed {} limited {} truncated {} failed {}",
+                stats.received.load(Ordering::Relaxed),
+                stats.echoed.load(Ordering::Relaxed),
+                stats.limited.load(Ordering::Relaxed),
+                stats.truncated.load(Ordering::Relaxed),
+                stats.failed.load(Ordering::Relaxed),
+            );
+            async {}
+        });
     }
 
-    impl Drop for UdpSocket {
-        fn drop(&mut self) {
-            let _ = Reactor::get().registry.deregister(&mut self.socket);
-        }
-    }
-    impl UdpSocket {
-        pub async fn recv_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
-            loop {
-                match self.socket.recv_from(buf) {
-                    Ok(value) => return Ok(value),
-                    Err(error) if error.kind() == ErrorKind::WouldBlock => {
-                        std::future::poll_fn(|cx| Reactor::get().poll(self.token, cx)).await?
-                    }
-                    Err(error) => return Err(error),
-                }
-            }
+    let drain = Drain::new();
+    let trigger = drain.clone();
+    spawner.spawn_named("shutdown-signal", async move {
+        if signals.recv().await.is_ok() {
+            trigger.begin();
         }
-    }
-    
-
-}
+    });
 
-fn main() {
-    let (executor, spawner) = new_executor_spawner();
-    spawner.spawn(async_main());
+    listener.serve(config, stats, spawner, drain.clone()).await?;
 
-    drop(spawner);
-    executor.run();
-
-}
-
-async fn async_main() {
-    let socket = executor::UdpSocket::bind("127.0.0.1:8000").unwrap();
-
-    let mut buf = [0; 10];
-    loop {
-        let (amt, src) = socket.recv_from(&mut buf).await.unwrap();
-
-        let buf = &mut buf[..amt];
-        println!("recv: {:?}", buf);
-        buf.reverse();
-        socket.send_to(buf, src).await.unwrap();
-    }
+    let report = drain.shutdown(args.grace).await;
+    eprintln!("shutdown: {report}");
+    Ok(())
 }
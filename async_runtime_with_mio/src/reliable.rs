@@ -0,0 +1,810 @@
+//! Reliable request/response over UDP.
+//!
+//! Every request carries a 64-bit id chosen by the client. The client
+//! retransmits with exponential backoff until the matching response comes
+//! back, and the server remembers recent ids per peer so a retransmitted
+//! request gets the cached response instead of running the handler again.
+//!
+//! Wire format, one message per datagram:
+//!
+//! ```text
+//! [kind: u8][id: u64 big-endian][payload]
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    io::{self, ErrorKind},
+    net::{SocketAddr, ToSocketAddrs},
+    pin::{pin, Pin},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    codec::MAX_DATAGRAM_SIZE,
+    executor::{
+        lock,
+        sync::{oneshot, Semaphore},
+        time::timeout_at,
+        Spawner, UdpSocket,
+    },
+};
+
+const REQUEST: u8 = 0;
+const RESPONSE: u8 = 1;
+const HEADER_LEN: usize = 9;
+
+fn encode(kind: u8, id: u64, payload: &[u8]) -> Vec<u8> {
+    let mut datagram = Vec::with_capacity(HEADER_LEN + payload.len());
+    datagram.push(kind);
+    datagram.extend_from_slice(&id.to_be_bytes());
+    datagram.extend_from_slice(payload);
+    datagram
+}
+
+fn decode(datagram: &[u8]) -> Option<(u8, u64, &[u8])> {
+    if datagram.len() < HEADER_LEN {
+        return None;
+    }
+    let id = u64::from_be_bytes(datagram[1..HEADER_LEN].try_into().unwrap());
+    Some((datagram[0], id, &datagram[HEADER_LEN..]))
+}
+
+/// When and how often a request is retransmitted.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// How long to wait for the first response.
+    pub initial_timeout: Duration,
+    /// Each retransmission waits this many times longer than the previous.
+    pub multiplier: u32,
+    /// Upper bound for a single wait.
+    pub max_timeout: Duration,
+    /// Total number of transmissions, the first one included.
+    pub max_attempts: u32,
+    /// Give up after this long, however many attempts are left.
+    pub deadline: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            initial_timeout: Duration::from_millis(100),
+            multiplier: 2,
+            max_timeout: Duration::from_secs(2),
+            max_attempts: 6,
+            deadline: Duration::from_secs(10),
+        }
+    }
+}
+
+type Pending = HashMap<u64, (SocketAddr, oneshot::Sender<Vec<u8>>)>;
+
+struct ClientShared {
+    socket: UdpSocket,
+    pending: Mutex<Pending>,
+    peers: Mutex<HashMap<SocketAddr, Arc<Semaphore>>>,
+    next_id: AtomicU64,
+    retransmits: AtomicU64,
+}
+
+/// Sends requests and matches responses to them by id.
+///
+/// A background task on the given spawner receives the responses; it
+/// stops once the client is dropped.
+pub struct Client {
+    shared: Arc<ClientShared>,
+    policy: RetryPolicy,
+    max_in_flight: usize,
+    _close: oneshot::Sender<()>,
+}
+
+impl Client {
+    pub fn bind(addr: impl ToSocketAddrs, spawner: &Spawner) -> io::Result<Self> {
+        let shared = Arc::new(ClientShared {
+            socket: UdpSocket::bind(addr)?,
+            pending: Mutex::new(HashMap::new()),
+            peers: Mutex::new(HashMap::new()),
+            // ids from an earlier client on the same port must not match
+            next_id: AtomicU64::new(initial_id()),
+            retransmits: AtomicU64::new(0),
+        });
+
+        let (close, closed) = oneshot::channel();
+        spawner.spawn(receive_responses(shared.clone(), closed));
+
+        Ok(Client {
+            shared,
+            policy: RetryPolicy::default(),
+            max_in_flight: 16,
+            _close: close,
+        })
+    }
+
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// How many requests may wait for the same peer at once. Further
+    /// requests queue until one of them completes.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.shared.socket.local_addr()
+    }
+
+    /// Total retransmissions so far, across all requests.
+    pub fn retransmits(&self) -> u64 {
+        self.shared.retransmits.load(Ordering::Relaxed)
+    }
+
+    /// Sends `payload` to `peer` and waits for its response, retrying per
+    /// the policy. Fails with `TimedOut` once the retries are exhausted.
+    pub async fn request(&self, peer: SocketAddr, payload: &[u8]) -> io::Result<Vec<u8>> {
+        if payload.len() > MAX_DATAGRAM_SIZE - HEADER_LEN {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "request too large"));
+        }
+
+        let limit = self.peer_limit(peer);
+        let permit = limit.clone().acquire_owned().await;
+
+        let result = self.exchange(peer, payload).await;
+
+        drop(permit);
+        self.release_peer(peer, limit);
+        result
+    }
+
+    async fn exchange(&self, peer: SocketAddr, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, mut receiver) = oneshot::channel();
+        lock(&self.shared.pending).insert(id, (peer, sender));
+        let _pending = PendingGuard {
+            pending: &self.shared.pending,
+            id,
+        };
+
+        let datagram = encode(REQUEST, id, payload);
+        let policy = self.policy;
+        let deadline = later(policy.deadline);
+        let mut wait = policy.initial_timeout;
+
+        for attempt in 0..policy.max_attempts {
+            if attempt > 0 {
+                self.shared.retransmits.fetch_add(1, Ordering::Relaxed);
+            }
+            self.shared.socket.send_to(&datagram, peer).await?;
+
+            match timeout_at(deadline.min(later(wait)), &mut receiver).await {
+                Ok(Ok(response)) => return Ok(response),
+                Ok(Err(oneshot::Canceled)) => {
+                    return Err(io::Error::other("response receiver stopped"))
+                }
+                Err(_) if Instant::now() >= deadline => break,
+                Err(_) => {}
+            }
+
+            wait = wait
+                .saturating_mul(policy.multiplier)
+                .min(policy.max_timeout);
+        }
+
+        Err(io::Error::new(
+            ErrorKind::TimedOut,
+            format!("no response from {peer} for request {id}"),
+        ))
+    }
+
+    fn peer_limit(&self, peer: SocketAddr) -> Arc<Semaphore> {
+        lock(&self.shared.peers)
+            .entry(peer)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_in_flight)))
+            .clone()
+    }
+
+    /// Forgets the peer's semaphore once nobody is using it.
+    fn release_peer(&self, peer: SocketAddr, limit: Arc<Semaphore>) {
+        let mut peers = lock(&self.shared.peers);
+        drop(limit);
+        if peers
+            .get(&peer)
+            .is_some_and(|limit| Arc::strong_count(limit) == 1)
+        {
+            peers.remove(&peer);
+        }
+    }
+}
+
+/// `after` from now, or for a policy asking for longer than an `Instant`
+/// can hold, a century from now.
+fn later(after: Duration) -> Instant {
+    const CENTURY: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+    let now = Instant::now();
+    now.checked_add(after).unwrap_or(now + CENTURY)
+}
+
+/// Removes a request from the pending table however `exchange` ends.
+struct PendingGuard<'a> {
+    pending: &'a Mutex<Pending>,
+    id: u64,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        lock(self.pending).remove(&self.id);
+    }
+}
+
+fn initial_id() -> u64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    (nanos as u64) << 16
+}
+
+async fn receive_responses(shared: Arc<ClientShared>, mut closed: oneshot::Receiver<()>) {
+    let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+
+    loop {
+        let received = {
+            let mut recv = pin!(shared.socket.recv_from(&mut buf));
+            std::future::poll_fn(|cx| {
+                if Pin::new(&mut closed).poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+                recv.as_mut().poll(cx).map(Some)
+            })
+            .await
+        };
+
+        let (amt, from) = match received {
+            None => return,
+            Some(Ok(received)) => received,
+            // e.g. ICMP port unreachable from an earlier send
+            Some(Err(_)) => continue,
+        };
+
+        let Some((RESPONSE, id, payload)) = decode(&buf[..amt]) else {
+            continue;
+        };
+
+        let mut pending = lock(&shared.pending);
+        // late duplicates of an answered request find nothing here
+        if pending.get(&id).is_some_and(|(peer, _)| *peer == from) {
+            let (_, sender) = pending.remove(&id).unwrap();
+            drop(pending);
+            let _ = sender.send(payload.to_vec());
+        }
+    }
+}
+
+// Begin Implementing the Server
+
+type BoxFuture = Pin<Box<dyn Future<Output = Vec<u8>> + Send + 'static>>;
+type Handler = Arc<dyn Fn(Vec<u8>, SocketAddr) -> BoxFuture + Send + Sync + 'static>;
+type SourceFilter = Box<dyn FnMut(SocketAddr) -> bool + Send + 'static>;
+
+enum Entry {
+    /// The handler is still running; retransmissions are dropped.
+    InFlight,
+    /// The response, resent for every retransmission.
+    Done(Vec<u8>),
+}
+
+/// Recently seen requests, bounded both in size and in age.
+struct DedupCache {
+    entries: HashMap<(SocketAddr, u64), (Entry, Instant)>,
+    order: VecDeque<(SocketAddr, u64)>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+/// What the server already knows about a request.
+enum Seen {
+    New,
+    InFlight,
+    /// The encoded response datagram.
+    Done(Vec<u8>),
+}
+
+impl DedupCache {
+    /// Looks `key` up, starting to track it if it's new.
+    fn check(&mut self, key: (SocketAddr, u64), now: Instant) -> Seen {
+        self.evict(now);
+
+        match self.entries.get(&key) {
+            Some((Entry::InFlight, _)) => Seen::InFlight,
+            Some((Entry::Done(response), _)) => Seen::Done(encode(RESPONSE, key.1, response)),
+            None => {
+                self.entries.insert(key, (Entry::InFlight, now));
+                self.order.push_back(key);
+                Seen::New
+            }
+        }
+    }
+
+    fn evict(&mut self, now: Instant) {
+        // each key is looked at once at most, however many are in flight
+        let mut unchecked = self.order.len();
+
+        while let Some(key) = self.order.front() {
+            if unchecked == 0 {
+                break;
+            }
+            unchecked -= 1;
+
+            let expired = match self.entries.get(key) {
+                // a running handler stays tracked, it just moves to the back
+                Some((Entry::InFlight, _)) => {
+                    let key = self.order.pop_front().unwrap();
+                    self.order.push_back(key);
+                    continue;
+                }
+                Some((Entry::Done(_), at)) => now.duration_since(*at) >= self.ttl,
+                None => true,
+            };
+            if !expired && self.entries.len() <= self.capacity {
+                break;
+            }
+
+            let key = self.order.pop_front().unwrap();
+            self.entries.remove(&key);
+        }
+    }
+}
+
+/// Serves requests with an async handler, answering each request id once.
+pub struct Server {
+    socket: Arc<UdpSocket>,
+    capacity: usize,
+    ttl: Duration,
+    max_handlers: usize,
+    filter: Option<SourceFilter>,
+    duplicates: Arc<AtomicU64>,
+}
+
+impl Server {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Server {
+            socket: Arc::new(UdpSocket::bind(addr)?),
+            capacity: 4096,
+            ttl: Duration::from_secs(30),
+            max_handlers: 256,
+            filter: None,
+            duplicates: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// How many answered requests to remember, and for how long. Should
+    /// outlast the clients' retry deadline.
+    pub fn with_dedup(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.capacity = capacity;
+        self.ttl = ttl;
+        self
+    }
+
+    /// How many handlers may run at once. Past that, no more requests are
+    /// received until one finishes, and the clients retransmit.
+    pub fn with_max_handlers(mut self, max_handlers: usize) -> Self {
+        self.max_handlers = max_handlers.max(1);
+        self
+    }
+
+    /// Drops every request, retransmissions included, from a source
+    /// `filter` turns away, e.g. one over a rate limit.
+    pub fn with_source_filter(
+        mut self,
+        filter: impl FnMut(SocketAddr) -> bool + Send + 'static,
+    ) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// A counter of suppressed duplicate requests, readable while serving.
+    pub fn duplicates(&self) -> Arc<AtomicU64> {
+        self.duplicates.clone()
+    }
+
+    /// Receives requests forever, running each new one on its own task,
+    /// up to the handler limit.
+    pub async fn serve<F, Fut>(mut self, spawner: Spawner, handler: F) -> io::Result<()>
+    where
+        F: Fn(Vec<u8>, SocketAddr) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Vec<u8>> + Send + 'static,
+    {
+        let handler: Handler = Arc::new(move |payload, from| Box::pin(handler(payload, from)));
+        let cache = Arc::new(Mutex::new(DedupCache {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            capacity: self.capacity,
+            ttl: self.ttl,
+        }));
+        // also bounds the requests in flight the cache keeps cycling through
+        let handlers = Arc::new(Semaphore::new(self.max_handlers));
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+
+        loop {
+            let (amt, from) = match self.socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(error) if error.kind() == ErrorKind::ConnectionRefused => continue,
+                Err(error) => return Err(error),
+            };
+            let Some((REQUEST, id, payload)) = decode(&buf[..amt]) else {
+                continue;
+            };
+            if self.filter.as_mut().is_some_and(|admit| !admit(from)) {
+                continue;
+            }
+
+            let key = (from, id);
+            let seen = lock(&cache).check(key, Instant::now());
+
+            match seen {
+                Seen::New => {}
+                Seen::InFlight => {
+                    self.duplicates.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                Seen::Done(response) => {
+                    self.duplicates.fetch_add(1, Ordering::Relaxed);
+                    let _ = self.socket.send_to(&response, from).await;
+                    continue;
+                }
+            }
+
+            let request = handler(payload.to_vec(), from);
+            let permit = handlers.clone().acquire_owned().await;
+            let (socket, cache) = (self.socket.clone(), cache.clone());
+            spawner.spawn(async move {
+                let response = request.await;
+                drop(permit);
+                let datagram = encode(RESPONSE, id, &response);
+
+                lock(&cache)
+                    .entries
+                    .insert(key, (Entry::Done(response), Instant::now()));
+                let _ = socket.send_to(&datagram, from).await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{sleep, Builder, DriverMode};
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            initial_timeout: Duration::from_millis(10),
+            multiplier: 2,
+            max_timeout: Duration::from_millis(80),
+            max_attempts: 8,
+            deadline: Duration::from_secs(2),
+        }
+    }
+
+    /// The same service as the echo server in `main`: reverse the payload.
+    fn reverse_server(spawner: &Spawner) -> (SocketAddr, Arc<AtomicU64>, Arc<AtomicU64>) {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let duplicates = server.duplicates();
+        let calls = Arc::new(AtomicU64::new(0));
+
+        let counted = calls.clone();
+        spawner.spawn({
+            let spawner = spawner.clone();
+            async move {
+                let _ = server
+                    .serve(spawner, move |mut payload, _| {
+                        counted.fetch_add(1, Ordering::SeqCst);
+                        async move {
+                            payload.reverse();
+                            payload
+                        }
+                    })
+                    .await;
+            }
+        });
+
+        (addr, calls, duplicates)
+    }
+
+    /// Forwards datagrams between one client and `server`, dropping every
+    /// other one going in either direction.
+    fn lossy_relay(spawner: &Spawner, server: SocketAddr) -> SocketAddr {
+        let relay = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = relay.local_addr().unwrap();
+
+        spawner.spawn(async move {
+            let mut client = None;
+            // counted per direction, or responses could always land on a drop
+            let mut forwarded = [0u64; 2];
+            let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+
+            loop {
+                let (amt, from) = relay.recv_from(&mut buf).await.unwrap();
+                let (to, direction) = if from == server {
+                    match client {
+                        Some(client) => (client, 1),
+                        None => continue,
+                    }
+                } else {
+                    client = Some(from);
+                    (server, 0)
+                };
+
+                forwarded[direction] += 1;
+                if forwarded[direction] % 2 == 1 {
+                    continue;
+                }
+                relay.send_to(&buf[..amt], to).await.unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn a_request_in_flight_does_not_stop_eviction() {
+        let mut cache = DedupCache {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            capacity: 4,
+            ttl: Duration::from_secs(30),
+        };
+        let peer: SocketAddr = "127.0.0.1:9".parse().unwrap();
+        let start = Instant::now();
+
+        // never answered
+        assert!(matches!(cache.check((peer, 0), start), Seen::New));
+        for id in 1..=10 {
+            assert!(matches!(cache.check((peer, id), start), Seen::New));
+            cache
+                .entries
+                .insert((peer, id), (Entry::Done(Vec::new()), start));
+        }
+        // evicting runs before tracking the new key
+        assert!(cache.entries.len() <= 5, "{}", cache.entries.len());
+        assert!(cache.entries.contains_key(&(peer, 0)));
+
+        // and answered requests still age out behind it
+        cache.check((peer, 11), start + Duration::from_secs(31));
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.entries.contains_key(&(peer, 0)));
+    }
+
+    #[test]
+    fn requests_get_the_reversed_payload() {
+        for mode in [DriverMode::ReactorThread, DriverMode::CurrentThread] {
+            let (executor, spawner) = Builder::new().mode(mode).build().unwrap();
+            let (server, calls, _) = reverse_server(&spawner);
+
+            let responses = executor.block_on(async move {
+                let client = Client::bind("127.0.0.1:0", &spawner).unwrap();
+                let mut responses = Vec::new();
+                for message in ["hello", "reliable", "udp"] {
+                    responses.push(client.request(server, message.as_bytes()).await.unwrap());
+                }
+                responses
+            });
+
+            assert_eq!(responses, [&b"olleh"[..], b"elbailer", b"pdu"]);
+            assert_eq!(calls.load(Ordering::SeqCst), 3);
+        }
+    }
+
+    #[test]
+    fn lost_datagrams_are_retransmitted_and_answered_once() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+        let (server, calls, duplicates) = reverse_server(&spawner);
+        let relay = lossy_relay(&spawner, server);
+
+        let (responses, retransmits) = executor.block_on(async move {
+            let client = Client::bind("127.0.0.1:0", &spawner)
+                .unwrap()
+                .with_policy(fast_policy());
+            let mut responses = Vec::new();
+            for i in 0..10u8 {
+                responses.push(client.request(relay, &[i, 0xff]).await.unwrap());
+            }
+            (responses, client.retransmits())
+        });
+
+        let expected: Vec<Vec<u8>> = (0..10u8).map(|i| vec![0xff, i]).collect();
+        assert_eq!(responses, expected);
+        assert!(retransmits >= 10, "{retransmits} retransmits");
+        // every lost response made the server see a duplicate request
+        assert_eq!(calls.load(Ordering::SeqCst), 10);
+        assert!(duplicates.load(Ordering::SeqCst) >= 5);
+    }
+
+    #[test]
+    fn duplicate_requests_get_the_cached_response() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+        let (server, calls, duplicates) = reverse_server(&spawner);
+
+        let responses = executor.block_on(async move {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let request = encode(REQUEST, 7, b"abc");
+            let mut responses = Vec::new();
+            let mut buf = [0; 64];
+
+            for _ in 0..3 {
+                socket.send_to(&request, server).await.unwrap();
+                let (amt, _) = socket.recv_from(&mut buf).await.unwrap();
+                responses.push(buf[..amt].to_vec());
+            }
+            responses
+        });
+
+        assert!(responses
+            .iter()
+            .all(|response| *response == encode(RESPONSE, 7, b"cba")));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(duplicates.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn requests_to_a_silent_peer_time_out() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let peer = silent.local_addr().unwrap();
+
+        let error = executor.block_on(async move {
+            let client = Client::bind("127.0.0.1:0", &spawner)
+                .unwrap()
+                .with_policy(RetryPolicy {
+                    max_attempts: 3,
+                    ..fast_policy()
+                });
+            client.request(peer, b"anyone?").await.unwrap_err()
+        });
+
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+
+        silent.set_nonblocking(true).unwrap();
+        let mut buf = [0; 64];
+        let mut attempts = 0;
+        while silent.recv(&mut buf).is_ok() {
+            attempts += 1;
+        }
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn a_policy_without_limits_does_not_overflow() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let peer = silent.local_addr().unwrap();
+
+        let error = executor.block_on(async move {
+            let client = Client::bind("127.0.0.1:0", &spawner)
+                .unwrap()
+                .with_policy(RetryPolicy {
+                    multiplier: u32::MAX,
+                    max_attempts: 2,
+                    deadline: Duration::MAX,
+                    ..fast_policy()
+                });
+            client.request(peer, b"anyone?").await.unwrap_err()
+        });
+
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn handlers_are_limited_across_clients() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        let server = Server::bind("127.0.0.1:0").unwrap().with_max_handlers(2);
+        let addr = server.local_addr().unwrap();
+        let active = Arc::new(AtomicU64::new(0));
+        let peak = Arc::new(AtomicU64::new(0));
+        let (counted, seen) = (active.clone(), peak.clone());
+        let serving = server.serve(spawner.clone(), move |payload, _| {
+            let (active, peak) = (counted.clone(), seen.clone());
+            async move {
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                sleep(Duration::from_millis(20)).await;
+                active.fetch_sub(1, Ordering::SeqCst);
+                payload
+            }
+        });
+        spawner.spawn(async move {
+            let _ = serving.await;
+        });
+
+        executor.block_on({
+            let spawner = spawner.clone();
+            async move {
+                // a client each, as a flood of fresh ids would look
+                let mut done = Vec::new();
+                for i in 0..6u8 {
+                    let (sender, receiver) = oneshot::channel();
+                    let client = Client::bind("127.0.0.1:0", &spawner)
+                        .unwrap()
+                        .with_policy(fast_policy());
+                    spawner.spawn(async move {
+                        let response = client.request(addr, &[i]).await.unwrap();
+                        let _ = sender.send(response);
+                    });
+                    done.push(receiver);
+                }
+                for (i, receiver) in done.into_iter().enumerate() {
+                    assert_eq!(receiver.await.unwrap(), [i as u8]);
+                }
+            }
+        });
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn in_flight_requests_are_limited_per_peer() {
+        let (executor, spawner) = Builder::new().current_thread().build().unwrap();
+
+        // a slow handler that records how many requests it serves at once
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let active = Arc::new(AtomicU64::new(0));
+        let peak = Arc::new(AtomicU64::new(0));
+        let (counted, seen) = (active.clone(), peak.clone());
+        let serving = server.serve(spawner.clone(), move |payload, _| {
+            let (active, peak) = (counted.clone(), seen.clone());
+            async move {
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                sleep(Duration::from_millis(20)).await;
+                active.fetch_sub(1, Ordering::SeqCst);
+                payload
+            }
+        });
+        spawner.spawn(async move {
+            let _ = serving.await;
+        });
+
+        executor.block_on({
+            let spawner = spawner.clone();
+            async move {
+                let client = Arc::new(
+                    Client::bind("127.0.0.1:0", &spawner)
+                        .unwrap()
+                        .with_policy(fast_policy())
+                        .with_max_in_flight(2),
+                );
+
+                let mut done = Vec::new();
+                for i in 0..6u8 {
+                    let (sender, receiver) = oneshot::channel();
+                    let client = client.clone();
+                    spawner.spawn(async move {
+                        let response = client.request(addr, &[i]).await.unwrap();
+                        let _ = sender.send(response);
+                    });
+                    done.push(receiver);
+                }
+                for (i, receiver) in done.into_iter().enumerate() {
+                    assert_eq!(receiver.await.unwrap(), [i as u8]);
+                }
+            }
+        });
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,292 @@
+//! Drives the echo server binary over loopback with plain std sockets.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpStream, UdpSocket},
+    process::{Child, ChildStderr, Command, Stdio},
+    time::Duration,
+};
+
+/// The running binary, killed when dropped.
+struct Server {
+    child: Child,
+    addr: SocketAddr,
+}
+
+impl Server {
+    /// Starts the server on a free port with `args` and waits until it
+    /// reports its address.
+    fn start(args: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_async_runtime_with_mio"))
+            .args(["--port", "0", "--no-status"])
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+
+        // "listening on udp://127.0.0.1:34567 (reverse)"
+        let addr = line
+            .split("://")
+            .nth(1)
+            .and_then(|rest| rest.split_whitespace().next())
+            .unwrap_or_else(|| panic!("unexpected banner {line:?}"))
+            .parse()
+            .unwrap();
+
+        Server { child, addr }
+    }
+
+    fn stderr(&mut self) -> ChildStderr {
+        self.child.stderr.take().unwrap()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn udp_client(server: SocketAddr) -> UdpSocket {
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    client
+}
+
+fn udp_exchange(client: &UdpSocket, message: &[u8]) -> Vec<u8> {
+    client.send(message).unwrap();
+    let mut buf = [0; 1024];
+    let n = client.recv(&mut buf).unwrap();
+    buf[..n].to_vec()
+}
+
+#[test]
+fn reverses_datagrams_by_default() {
+    let server = Server::start(&[]);
+    let client = udp_client(server.addr);
+
+    assert_eq!(udp_exchange(&client, b"hello world"), b"dlrow olleh");
+}
+
+#[test]
+fn uppercase_mode_over_the_current_thread_driver() {
+    let server = Server::start(&["--mode", "uppercase", "--current-thread"]);
+    let client = udp_client(server.addr);
+
+    assert_eq!(udp_exchange(&client, b"Mixed case 123"), b"MIXED CASE 123");
+}
+
+#[test]
+fn discard_mode_never_answers() {
+    let server = Server::start(&["-m", "discard"]);
+    let client = udp_client(server.addr);
+    client
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+
+    client.send(b"into the void").unwrap();
+    let mut buf = [0; 64];
+    assert!(client.recv(&mut buf).is_err());
+}
+
+#[test]
+fn oversized_datagrams_are_dropped() {
+    let server = Server::start(&["--mode", "echo", "--buffer-size", "4"]);
+    let client = udp_client(server.addr);
+
+    client.send(b"too long").unwrap();
+    assert_eq!(udp_exchange(&client, b"tiny"), b"tiny");
+}
+
+#[test]
+fn sources_over_the_rate_limit_are_ignored() {
+    let server = Server::start(&["--mode", "echo", "--rate", "1", "--burst", "3"]);
+    let client = udp_client(server.addr);
+    client
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+
+    for i in 0..10u8 {
+        client.send(&[i]).unwrap();
+    }
+    let mut replies = Vec::new();
+    let mut buf = [0; 8];
+    while let Ok(n) = client.recv(&mut buf) {
+        replies.extend_from_slice(&buf[..n]);
+    }
+
+    assert_eq!(replies, [0, 1, 2]);
+}
+
+#[test]
+fn the_leaky_bucket_policy_limits_sources_too() {
+    let server = Server::start(&[
+        "--mode",
+        "echo",
+        "--rate",
+        "1",
+        "--burst",
+        "2",
+        "--limiter",
+        "leaky",
+    ]);
+    let client = udp_client(server.addr);
+    client
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+
+    for i in 0..10u8 {
+        client.send(&[i]).unwrap();
+    }
+    let mut replies = Vec::new();
+    let mut buf = [0; 8];
+    while let Ok(n) = client.recv(&mut buf) {
+        replies.extend_from_slice(&buf[..n]);
+    }
+
+    assert_eq!(replies, [0, 1]);
+}
+
+#[test]
+fn reliable_sources_are_rate_limited() {
+    let server = Server::start(&[
+        "--transport",
+        "reliable",
+        "--mode",
+        "echo",
+        "--rate",
+        "1",
+        "--burst",
+        "2",
+    ]);
+    let client = udp_client(server.addr);
+    client
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+
+    // a request is `[0][id: u64 big-endian][payload]`, each with a new id
+    for i in 0..10u8 {
+        let mut request = vec![0; 9];
+        request[8] = i;
+        request.push(i);
+        client.send(&request).unwrap();
+    }
+    let mut replies = Vec::new();
+    let mut buf = [0; 16];
+    while let Ok(n) = client.recv(&mut buf) {
+        replies.extend_from_slice(&buf[9..n]);
+    }
+
+    assert_eq!(replies, [0, 1]);
+}
+
+#[test]
+fn rejects_a_negative_or_nan_rate() {
+    for rate in ["-5", "NaN", "inf"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_async_runtime_with_mio"))
+            .arg(format!("--rate={rate}"))
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success(), "{rate}");
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("not a number of replies per second"),
+            "{rate}"
+        );
+    }
+}
+
+#[test]
+fn rejects_out_of_range_grace_and_buffer_sizes() {
+    for arg in [
+        "--grace=-1",
+        "--grace=NaN",
+        "--grace=1e30",
+        "--buffer-size=0",
+        "--buffer-size=65536",
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_async_runtime_with_mio"))
+            .arg(arg)
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success(), "{arg}");
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("invalid value"),
+            "{arg}"
+        );
+    }
+}
+
+#[test]
+fn echoes_over_tcp() {
+    let server = Server::start(&["--transport", "tcp", "--mode", "echo"]);
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    for message in [&b"first"[..], b"second"] {
+        stream.write_all(message).unwrap();
+        let mut reply = vec![0; message.len()];
+        stream.read_exact(&mut reply).unwrap();
+        assert_eq!(reply, message);
+    }
+}
+
+#[test]
+fn sigterm_drains_open_connections() {
+    let mut server = Server::start(&["--transport", "tcp", "--mode", "echo"]);
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream.write_all(b"ping").unwrap();
+    stream.read_exact(&mut [0; 4]).unwrap();
+
+    unsafe { libc::kill(server.child.id() as libc::pid_t, libc::SIGTERM) };
+    assert!(server.child.wait().unwrap().success());
+
+    // the idle connection was closed rather than canceled
+    assert_eq!(stream.read(&mut [0; 4]).unwrap(), 0);
+    let mut stderr = String::new();
+    server.stderr().read_to_string(&mut stderr).unwrap();
+    assert!(
+        stderr.contains("shutdown: 1 completed, 0 canceled, 0 rejected"),
+        "{stderr:?}"
+    );
+}
+
+#[test]
+fn verbose_logs_each_message() {
+    let mut server = Server::start(&["-vv"]);
+    let client = udp_client(server.addr);
+    udp_exchange(&client, b"ping");
+
+    let mut line = String::new();
+    BufReader::new(server.stderr())
+        .read_line(&mut line)
+        .unwrap();
+    assert!(line.starts_with("recv from 127.0.0.1:"), "{line:?}");
+}
+
+#[test]
+fn rejects_an_unknown_mode() {
+    let output = Command::new(env!("CARGO_BIN_EXE_async_runtime_with_mio"))
+        .args(["--mode", "shout"])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown mode `shout`"));
+}
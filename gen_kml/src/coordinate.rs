@@ -0,0 +1,309 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A position in decimal degrees, north and east positive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coordinate {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoordinateError {
+    /// Not a format we know.
+    Unrecognized(String),
+    /// An NMEA sentence whose `*hh` checksum doesn't match.
+    Checksum(String),
+    /// An NMEA sentence from a receiver without a fix.
+    NoFix(String),
+    /// The minutes of a `ddmm.mmmm` value are 60 or more.
+    Minutes(f64),
+    Latitude(f64),
+    Longitude(f64),
+}
+
+impl fmt::Display for CoordinateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoordinateError::Unrecognized(s) => write!(f, "unrecognized coordinates {:?}", s),
+            CoordinateError::Checksum(s) => write!(f, "bad checksum in {:?}", s),
+            CoordinateError::NoFix(s) => write!(f, "no GPS fix in {:?}", s),
+            CoordinateError::Minutes(m) => write!(f, "{} is not a number of minutes", m),
+            CoordinateError::Latitude(lat) => write!(f, "latitude {} is out of range", lat),
+            CoordinateError::Longitude(lng) => write!(f, "longitude {} is out of range", lng),
+        }
+    }
+}
+
+impl Error for CoordinateError {}
+
+/// Converts NMEA `ddmm.mmmm` (or `dddmm.mmmm`) to decimal degrees, keeping
+/// the sign.
+fn nmea_to_degrees(value: f64) -> Result<f64, CoordinateError> {
+    let degrees = (value.abs() / 100.0).trunc();
+    let minutes = value.abs() - degrees * 100.0;
+    if !(0.0..60.0).contains(&minutes) {
+        return Err(CoordinateError::Minutes(minutes));
+    }
+    Ok((degrees + minutes / 60.0).copysign(value))
+}
+
+/// Negates `value` in the `negative` hemisphere; `None` if `hemisphere` is
+/// neither of the two.
+fn signed(value: f64, hemisphere: &str, positive: &str, negative: &str) -> Option<f64> {
+    match hemisphere {
+        h if h == positive => Some(value),
+        h if h == negative => Some(-value),
+        _ => None,
+    }
+}
+
+impl Coordinate {
+    pub fn new(latitude: f64, longitude: f64) -> Result<Self, CoordinateError> {
+        if !(-90.0..=90.0).contains(&latitude) {
+            return Err(CoordinateError::Latitude(latitude));
+        }
+        if !(-180.0..=180.0).contains(&longitude) {
+            return Err(CoordinateError::Longitude(longitude));
+        }
+        Ok(Coordinate {
+            latitude,
+            longitude,
+        })
+    }
+
+    /// From the `ddmm.mmmm` latitude and `dddmm.mmmm` longitude GPS
+    /// receivers report, negative south and west.
+    pub fn from_nmea(latitude: f64, longitude: f64) -> Result<Self, CoordinateError> {
+        Coordinate::new(nmea_to_degrees(latitude)?, nmea_to_degrees(longitude)?)
+    }
+}
+
+/// Checks the `*hh` checksum of an NMEA sentence, if it has one, and splits
+/// the rest into fields, the first being the talker and sentence type.
+fn nmea_fields(sentence: &str) -> Result<Vec<&str>, CoordinateError> {
+    let unrecognized = || CoordinateError::Unrecognized(sentence.to_string());
+
+    let body = sentence.strip_prefix('$').ok_or_else(unrecognized)?;
+    let body = match body.split_once('*') {
+        Some((body, checksum)) => {
+            let expected = u8::from_str_radix(checksum, 16).map_err(|_| unrecognized())?;
+            if body.bytes().fold(0, |sum, b| sum ^ b) != expected {
+                return Err(CoordinateError::Checksum(sentence.to_string()));
+            }
+            body
+        }
+        None => body,
+    };
+    Ok(body.split(',').collect())
+}
+
+/// Parses the position out of a `$GPGGA` or `$GPRMC` sentence, or the same
+/// from other talkers, e.g. `$GNRMC`.
+fn from_sentence(sentence: &str) -> Result<Coordinate, CoordinateError> {
+    let unrecognized = || CoordinateError::Unrecognized(sentence.to_string());
+    let no_fix = || CoordinateError::NoFix(sentence.to_string());
+
+    let fields = nmea_fields(sentence)?;
+    let position = match (fields[0].get(2..), fields.len()) {
+        // fix quality 0 is no fix
+        (Some("GGA"), 7..) if fields[6] == "0" => return Err(no_fix()),
+        (Some("GGA"), 7..) => &fields[2..6],
+        // status V is a void fix
+        (Some("RMC"), 7..) if fields[2] != "A" => return Err(no_fix()),
+        (Some("RMC"), 7..) => &fields[3..7],
+        _ => return Err(unrecognized()),
+    };
+
+    let lat = position[0].parse::<f64>().map_err(|_| unrecognized())?;
+    let lng = position[2].parse::<f64>().map_err(|_| unrecognized())?;
+    let lat = signed(lat, position[1], "N", "S").ok_or_else(unrecognized)?;
+    let lng = signed(lng, position[3], "E", "W").ok_or_else(unrecognized)?;
+
+    Coordinate::from_nmea(lat, lng)
+}
+
+/// Parses a gateway's `gps` string, in any of:
+///
+/// - `lat=4456.26222 N ,lng=08902.03021 E`, degrees and minutes, N/S and E/W
+/// - `44.937704, -89.033837`, signed decimal degrees
+/// - a raw `$GPGGA` or `$GPRMC` sentence
+impl FromStr for Coordinate {
+    type Err = CoordinateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lazy_static! {
+            static ref LABELLED: Regex =
+                Regex::new(r#"lat=(\d+\.\d+)\s*([NS])\s*,\s*lng=(\d+\.\d+)\s*([EW])"#).unwrap();
+            static ref DECIMAL: Regex =
+                Regex::new(r#"^([+-]?\d+\.\d+)\s*[,\s]\s*([+-]?\d+\.\d+)$"#).unwrap();
+        }
+        let s = s.trim();
+        let unrecognized = || CoordinateError::Unrecognized(s.to_string());
+
+        if s.starts_with('$') {
+            return from_sentence(s);
+        }
+
+        if let Some(caps) = LABELLED.captures(s) {
+            let lat = caps[1].parse::<f64>().map_err(|_| unrecognized())?;
+            let lng = caps[3].parse::<f64>().map_err(|_| unrecognized())?;
+            let lat = signed(lat, &caps[2], "N", "S").ok_or_else(unrecognized)?;
+            let lng = signed(lng, &caps[4], "E", "W").ok_or_else(unrecognized)?;
+            return Coordinate::from_nmea(lat, lng);
+        }
+
+        if let Some(caps) = DECIMAL.captures(s) {
+            let lat = caps[1].parse::<f64>().map_err(|_| unrecognized())?;
+            let lng = caps[2].parse::<f64>().map_err(|_| unrecognized())?;
+            return Coordinate::new(lat, lng);
+        }
+
+        Err(unrecognized())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn degrees_and_minutes_become_decimal_degrees() {
+        let c: Coordinate = "lat=4456.26222 N ,lng=08902.03021 E".parse().unwrap();
+        // 44° 56.26222', 89° 2.03021'
+        assert_near(c.latitude, 44.937704);
+        assert_near(c.longitude, 89.033837);
+
+        let c: Coordinate = "lat=4419.07937 N,lng=08835.44603 E,star=6".parse().unwrap();
+        assert_near(c.latitude, 44.317990);
+        assert_near(c.longitude, 88.590767);
+    }
+
+    #[test]
+    fn every_gateway_in_the_input_is_near_the_others() {
+        let gateways: Vec<serde_json::Value> =
+            serde_json::from_str(include_str!("../input.json")).unwrap();
+
+        let mut parsed = 0;
+        for gps in gateways.iter().filter_map(|gw| gw["gps"].as_str()) {
+            let c: Coordinate = gps.parse().unwrap();
+            assert!((44.0..45.0).contains(&c.latitude), "{}: {:?}", gps, c);
+            assert!((88.0..90.0).contains(&c.longitude), "{}: {:?}", gps, c);
+            parsed += 1;
+        }
+        assert!(parsed > 0);
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        assert_eq!(
+            "lat=4475.00000 N,lng=08902.03021 E".parse::<Coordinate>(),
+            Err(CoordinateError::Minutes(75.0))
+        );
+        assert_eq!(
+            "lat=9130.00000 N,lng=08902.03021 E".parse::<Coordinate>(),
+            Err(CoordinateError::Latitude(91.5))
+        );
+        assert_eq!(
+            "lat=4456.26222 N,lng=18100.00000 E".parse::<Coordinate>(),
+            Err(CoordinateError::Longitude(181.0))
+        );
+        assert!(matches!(
+            "no fix".parse::<Coordinate>(),
+            Err(CoordinateError::Unrecognized(_))
+        ));
+        assert_eq!(
+            "-91.0, 10.0".parse::<Coordinate>(),
+            Err(CoordinateError::Latitude(-91.0))
+        );
+    }
+
+    #[test]
+    fn southern_and_western_hemispheres_are_negative() {
+        let c: Coordinate = "lat=3351.82000 S ,lng=15112.42000 E".parse().unwrap();
+        assert_near(c.latitude, -33.863667);
+        assert_near(c.longitude, 151.207);
+
+        let c: Coordinate = "lat=4042.76800 N,lng=07400.36000 W,star=9".parse().unwrap();
+        assert_near(c.latitude, 40.712800);
+        assert_near(c.longitude, -74.006);
+
+        let c: Coordinate = "lat=3436.21000 S,lng=05822.86000 W".parse().unwrap();
+        assert_near(c.latitude, -34.603500);
+        assert_near(c.longitude, -58.381);
+
+        assert!(matches!(
+            "lat=4456.26222 E,lng=08902.03021 N".parse::<Coordinate>(),
+            Err(CoordinateError::Unrecognized(_))
+        ));
+    }
+
+    #[test]
+    fn decimal_degrees_are_taken_as_they_are() {
+        for (s, lat, lng) in [
+            ("44.937704,89.033837", 44.937704, 89.033837),
+            ("-33.8636, 151.207", -33.8636, 151.207),
+            (" +40.7128 -74.006 ", 40.7128, -74.006),
+        ] {
+            assert_eq!(s.parse(), Ok(Coordinate::new(lat, lng).unwrap()), "{}", s);
+        }
+    }
+
+    #[test]
+    fn gga_and_rmc_sentences_carry_a_position() {
+        let gga = "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47";
+        let rmc = "$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A";
+        for sentence in [gga, rmc] {
+            let c: Coordinate = sentence.parse().unwrap();
+            assert_near(c.latitude, 48.1173);
+            assert_near(c.longitude, 11.516667);
+        }
+
+        let c: Coordinate =
+            "$GPGGA,002153.000,3342.6618,N,11751.3858,W,1,10,1.2,27.0,M,-34.2,M,,0000*5E"
+                .parse()
+                .unwrap();
+        assert_near(c.latitude, 33.711030);
+        assert_near(c.longitude, -117.856430);
+
+        // another talker, without a checksum
+        let c: Coordinate = "$GNRMC,225446,A,3351.8200,S,15112.4200,E,000.5,054.7,191194,020.3,E"
+            .parse()
+            .unwrap();
+        assert_near(c.latitude, -33.863667);
+        assert_near(c.longitude, 151.207);
+    }
+
+    #[test]
+    fn sentences_without_a_fix_or_with_a_bad_checksum_are_rejected() {
+        assert!(matches!(
+            "$GPGGA,,,,,,0,00,99.99,,,,,,*48".parse::<Coordinate>(),
+            Err(CoordinateError::NoFix(_))
+        ));
+        assert!(matches!(
+            "$GPRMC,,V,,,,,,,,,,N*53".parse::<Coordinate>(),
+            Err(CoordinateError::NoFix(_))
+        ));
+        assert!(matches!(
+            "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*48"
+                .parse::<Coordinate>(),
+            Err(CoordinateError::Checksum(_))
+        ));
+        assert!(matches!(
+            "$GPGSV,3,1,11,03,03,111,00*4A".parse::<Coordinate>(),
+            Err(CoordinateError::Unrecognized(_))
+        ));
+    }
+}
@@ -1,15 +1,14 @@
-use kml::KmlDocument;
-use kml::{types::Point, Kml, KmlWriter};
+mod coordinate;
+
+use coordinate::Coordinate;
 use lazy_static::lazy_static;
-use regex::Regex;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::Error as SerError;
-use std::collections::HashMap;
 use std::error::Error;
 use std::io::Write;
 use std::{fs::File, io::Read};
-use tera::{to_value, try_get_value, Context, Tera, Value};
+use tera::{Context, Tera};
 
 #[derive(Debug, Deserialize, Serialize)]
 struct Gateway {
@@ -28,18 +27,6 @@ struct MyPoint {
     pub name: String,
 }
 
-fn extract_coordinates(s: &str) -> Option<(f64, f64)> {
-    lazy_static! {
-        static ref RE: Regex =
-            Regex::new(r#"lat=(-?\d+\.\d+) N\s*,\s*lng=(-?\d+\.\d+) E"#).unwrap();
-    }
-    let caps = RE.captures(s)?;
-    let lat = caps.get(1)?.as_str().parse::<f64>().ok()?;
-    let lng = caps.get(2)?.as_str().parse::<f64>().ok()?;
-
-    Some((lat, lng))
-}
-
 lazy_static! {
     pub static ref TEMPLATES: Tera = {
         let mut tera = match Tera::new("templates/**/*") {
@@ -78,9 +65,9 @@ fn main() {
     match gws {
         Ok(gws) => {
             for gw in gws.into_iter() {
-                match gw.gps {
-                    Some(gps) => {
-                        if let Some((lat, lng)) = extract_coordinates(&gps) {
+                if let Some(gps) = gw.gps {
+                    match gps.parse::<Coordinate>() {
+                        Ok(c) => {
                             let hoplist: String = gw
                                 .hoplist
                                 .iter()
@@ -89,19 +76,16 @@ fn main() {
                                 .join("|");
                             let name = format!("{}_{}_{}", gw.name, hoplist, gw.txpower);
                             let p = MyPoint {
-                                long: lng / 100.0,
-                                lati: lat / 100.0,
-                                name: name,
+                                long: c.longitude,
+                                lati: c.latitude,
+                                name,
                             };
                             points.push(p);
-                        } else {
-                            println!(
-                                "Could not extract coordinates from the given string {:?}",
-                                gw.name
-                            );
+                        }
+                        Err(e) => {
+                            println!("Could not extract coordinates of {:?}: {}", gw.name, e);
                         }
                     }
-                    None => {}
                 }
             }
         }
@@ -116,8 +100,8 @@ fn main() {
     match TEMPLATES.render("temp.kml", &context) {
         Ok(s) => {
             let mut file = File::create("output.kml").unwrap();
-            file.write_all(s.as_bytes());
-            file.flush();
+            file.write_all(s.as_bytes()).unwrap();
+            file.flush().unwrap();
         }
         Err(e) => {
             println!("Error: {}", e);
@@ -131,16 +115,7 @@ fn main() {
 }
 
 #[test]
-fn test_regx() -> () {
-    let s = "lat=4456.26233 N ,lng=08902.03126 E";
-    if let Some((lat, lng)) = extract_coordinates(s) {
-        println!("Latitude: {}, Longitude: {}", lat, lng);
-    } else {
-        println!("Could not extract coordinates from the given string");
-    }
-}
-
-#[test]
+#[allow(clippy::unused_unit)]
 fn test_one() -> () {
     let vec: Vec<u16> = vec![1, 2, 3];
     let str: String = vec